@@ -1,10 +1,25 @@
 extern crate serde_codegen;
 
 use std::env;
+use std::fs;
 use std::path::Path;
 
+/// Files with types requiring serde codegen, relative to the source directory.
+const SOURCES: &'static [&'static str] = &[
+    "config",
+    "source/config",
+    "output/config",
+    "filter/config",
+];
+
 pub fn main() {
     let out_dir = env::var_os("OUT_DIR").unwrap();
 
-    serde_codegen::expand(&Path::new("src/config.in.rs"), &Path::new(&out_dir).join("config.rs")).unwrap();
+    for name in SOURCES {
+        let src = Path::new("src").join(format!("{}.in.rs", name));
+        let dst = Path::new(&out_dir).join(format!("{}.rs", name));
+
+        fs::create_dir_all(dst.parent().unwrap()).unwrap();
+        serde_codegen::expand(&src, &dst).unwrap();
+    }
 }
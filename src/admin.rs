@@ -0,0 +1,253 @@
+//! Local administration API over a Unix socket.
+//!
+//! The protocol is line-based: the client sends a single command line, the server responds with
+//! either `OK` or `ERROR` status line, optionally followed by the response body, and closes the
+//! connection.
+//!
+//! Supported commands:
+//!  - `reload` - reread the config file and recreate the runtime, like SIGHUP does.
+//!  - `reopen` - reopen outputs, like SIGUSR1 does.
+//!  - `severity LEVEL` - change the logging severity to the given level.
+//!  - `pipelines` - list pipelines with their components.
+//!  - `stats` - dump internal metrics.
+//!  - `pause NAME` - pause processing records by the given pipeline.
+//!  - `resume NAME` - resume processing records by the given pipeline.
+//!  - `shutdown` - gracefully stop the runtime, like SIGTERM does.
+//!
+//! The server only parses commands, executing them is the responsibility of the receiver of
+//! requests, which is usually the main thread.
+
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chan;
+
+use severity::Severity;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Reload,
+    Reopen,
+    Severity(Severity),
+    Pipelines,
+    Stats,
+    Pause(String),
+    Resume(String),
+    Shutdown,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let mut parts = line.split_whitespace();
+
+        let command = match (parts.next(), parts.next()) {
+            (Some("reload"), None) => Command::Reload,
+            (Some("reopen"), None) => Command::Reopen,
+            (Some("severity"), Some(level)) => Command::Severity(try!(level.parse())),
+            (Some("pipelines"), None) => Command::Pipelines,
+            (Some("stats"), None) => Command::Stats,
+            (Some("pause"), Some(name)) => Command::Pause(name.to_owned()),
+            (Some("resume"), Some(name)) => Command::Resume(name.to_owned()),
+            (Some("shutdown"), None) => Command::Shutdown,
+            (Some("severity"), None) |
+            (Some("pause"), None) |
+            (Some("resume"), None) => return Err("argument required".into()),
+            (Some(command), _) => return Err(format!("invalid command '{}'", command)),
+            (None, _) => return Err("command required".into()),
+        };
+
+        if parts.next().is_some() {
+            return Err("too many arguments".into());
+        }
+
+        Ok(command)
+    }
+}
+
+/// Admin request, which should be executed and replied to.
+pub struct Request {
+    pub command: Command,
+    pub reply: mpsc::Sender<Result<String, String>>,
+}
+
+/// Handle, that allows components to request the graceful shutdown from the main loop.
+///
+/// The underlying channel must be unbounded, because the main loop may have already stopped
+/// receiving, i.e. while the runtime is being dropped on reload or shutdown.
+#[derive(Clone)]
+pub struct Shutdown {
+    tx: chan::Sender<()>,
+}
+
+impl Shutdown {
+    pub fn new(tx: chan::Sender<()>) -> Shutdown {
+        Shutdown {
+            tx: tx,
+        }
+    }
+
+    /// Requests the shutdown without waiting for the main loop.
+    pub fn request(&self) {
+        self.tx.send(());
+    }
+}
+
+pub struct Server {
+    path: PathBuf,
+    stopped: Arc<AtomicBool>,
+    /// Closed on drop to interrupt requests, that are waiting for the main loop.
+    stop: Option<chan::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    /// Binds the admin socket to the given path, forwarding all incoming requests into the given
+    /// channel.
+    ///
+    /// Stale socket left from the previous run is removed, but neither other files nor sockets of
+    /// running instances are touched. The socket is accessible by its owner only.
+    pub fn new<P: AsRef<Path>>(path: P, tx: chan::Sender<Request>) -> Result<Server, Box<Error>> {
+        let path = path.as_ref().to_path_buf();
+
+        if let Ok(meta) = fs::symlink_metadata(&path) {
+            if !meta.file_type().is_socket() {
+                return Err(format!("{} already exists and is not a socket", path.display()).into());
+            }
+
+            if UnixStream::connect(&path).is_ok() {
+                return Err(format!("{} is used by another running instance", path.display())
+                    .into());
+            }
+
+            try!(fs::remove_file(&path));
+        }
+
+        let listener = try!(UnixListener::bind(&path));
+        try!(fs::set_permissions(&path, fs::Permissions::from_mode(0o600)));
+        info!("exposed admin socket on {}", path.display());
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (stop, stop_rx) = chan::sync(0);
+        let thread = {
+            let stopped = stopped.clone();
+            thread::spawn(move || Server::run(listener, tx, stop_rx, stopped))
+        };
+
+        let server = Server {
+            path: path,
+            stopped: stopped,
+            stop: Some(stop),
+            thread: Some(thread),
+        };
+
+        Ok(server)
+    }
+
+    fn run(listener: UnixListener, tx: chan::Sender<Request>, stop: chan::Receiver<()>,
+        stopped: Arc<AtomicBool>)
+    {
+        for stream in listener.incoming() {
+            if stopped.load(Ordering::SeqCst) {
+                break;
+            }
+
+            match stream {
+                Ok(stream) => {
+                    if let Err(err) = Server::handle(stream, &tx, &stop) {
+                        warn!("failed to handle admin request: {}", err);
+                    }
+                }
+                Err(err) => {
+                    warn!("failed to accept admin connection: {}", err);
+                }
+            }
+        }
+
+        debug!("stopped admin socket listener");
+    }
+
+    fn handle(stream: UnixStream, tx: &chan::Sender<Request>, stop: &chan::Receiver<()>) ->
+        Result<(), Box<Error>>
+    {
+        try!(stream.set_read_timeout(Some(Duration::from_secs(5))));
+
+        let mut line = String::new();
+        try!(BufReader::new(&stream).read_line(&mut line));
+
+        debug!("admin request: {}", line.trim());
+
+        let result = match Command::parse(&line) {
+            Ok(command) => {
+                let (reply, rx) = mpsc::channel();
+                let request = Request { command: command, reply: reply };
+
+                // The main loop may have already exited, so never block on it during shutdown.
+                chan_select! {
+                    tx.send(request) => {},
+                    stop.recv() => return Err("admin listener is stopping".into()),
+                }
+
+                try!(rx.recv())
+            }
+            Err(err) => Err(err),
+        };
+
+        let mut wr = &stream;
+        match result {
+            Ok(body) => try!(write!(wr, "OK\n{}", body)),
+            Err(err) => try!(write!(wr, "ERROR\n{}\n", err)),
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+
+        // Interrupt the request waiting for the main loop, if any.
+        drop(self.stop.take());
+
+        // Wake up the listener, blocked on accept.
+        if let Err(err) = UnixStream::connect(&self.path) {
+            error!("failed to wake up admin listener: {}", err);
+            return;
+        }
+
+        if let Err(err) = self.thread.take().expect("thread must exist").join() {
+            error!("failed to gracefully stop admin listener: {:?}", err);
+        }
+
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!("failed to remove admin socket {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+/// Sends the given command line to the admin socket, returning the response body on success.
+pub fn call<P: AsRef<Path>>(path: P, command: &str) -> Result<String, Box<Error>> {
+    let mut stream = try!(UnixStream::connect(path));
+    try!(writeln!(stream, "{}", command));
+
+    let mut response = String::new();
+    try!(stream.read_to_string(&mut response));
+
+    let mut parts = response.splitn(2, '\n');
+    let status = parts.next().unwrap_or("");
+    let body = parts.next().unwrap_or("");
+
+    match status {
+        "OK" => Ok(body.to_owned()),
+        "ERROR" => Err(body.trim().to_owned().into()),
+        _ => Err(format!("unexpected response: {}", response).into()),
+    }
+}
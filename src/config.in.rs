@@ -1,47 +1,389 @@
+use std::collections::{BTreeMap, HashSet};
 use std::error::Error;
-use std::fs::File;
-use std::path::Path;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use serde::de::{self, Deserialize, Deserializer};
 use serde_json;
 
+use expr::Predicate;
+use interpolate;
+use severity::Severity;
+
 pub type Value = serde_json::Value;
 
+/// Decodes a typed component config.
+///
+/// The `type` field is ignored, because it is used only for component identification.
+pub fn decode<T: Deserialize>(cfg: &Value) -> Result<T, Box<Error>> {
+    let mut cfg = cfg.clone();
+
+    if let Some(map) = cfg.as_object_mut() {
+        map.remove("type");
+    }
+
+    serde_json::from_value(cfg).map_err(Into::into)
+}
+
+/// Pipeline name.
+///
+/// Must be non-empty and must not contain whitespace characters, because it is used both as a
+/// metrics label and as an admin command argument.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PipeName(String);
+
+impl PipeName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for PipeName {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(&self.0)
+    }
+}
+
+impl Deserialize for PipeName {
+    fn deserialize<D>(de: &mut D) -> Result<PipeName, D::Error>
+        where D: Deserializer
+    {
+        let name = try!(String::deserialize(de));
+
+        if name.is_empty() {
+            return Err(de::Error::custom("pipeline name must not be empty"));
+        }
+
+        // Unnamed pipelines are named by their index, i.e. "#0", so such names would collide.
+        if name.starts_with('#') {
+            return Err(de::Error::custom(format!("pipeline name '{}' must not start with '#'",
+                name)));
+        }
+
+        if name.chars().any(char::is_whitespace) {
+            return Err(de::Error::custom(format!("pipeline name '{}' must not contain whitespace",
+                name)));
+        }
+
+        Ok(PipeName(name))
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct PipeConfig {
+    /// Optional pipeline name, used mainly for metrics labelling and administration.
+    name: Option<PipeName>,
     sources: Vec<Value>,
+    /// Filters, applied in order.
+    #[serde(default)]
+    filters: Vec<Value>,
     outputs: Vec<Value>,
+    /// Routing table, which is evaluated in order until the first matching rule.
+    ///
+    /// If empty, every record is sent to all outputs.
+    #[serde(default)]
+    route: Vec<RouteConfig>,
+    /// Directory of the main config file, set after reading.
+    #[serde(skip_deserializing)]
+    base: PathBuf,
 }
 
 impl PipeConfig {
+    pub fn name(&self) -> Option<&PipeName> {
+        self.name.as_ref()
+    }
+
     pub fn sources(&self) -> &Vec<Value> {
         &self.sources
     }
 
+    pub fn filters(&self) -> &Vec<Value> {
+        &self.filters
+    }
+
     pub fn outputs(&self) -> &Vec<Value> {
         &self.outputs
     }
+
+    pub fn route(&self) -> &Vec<RouteConfig> {
+        &self.route
+    }
+
+    /// Returns the directory, which relative file paths in component configs are resolved
+    /// against.
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
 }
 
+/// Pipeline routing rule.
 #[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    /// Records matching this predicate are sent to the listed outputs. Matches everything if
+    /// omitted, which is useful for the last fallback rule.
+    when: Option<Predicate>,
+    /// Output names.
+    outputs: Vec<String>,
+}
+
+impl RouteConfig {
+    pub fn when(&self) -> Option<&Predicate> {
+        self.when.as_ref()
+    }
+
+    pub fn outputs(&self) -> &Vec<String> {
+        &self.outputs
+    }
+}
+
+/// Routing options, that can be specified for any output alongside its own config.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputRoute {
+    /// Output name for referencing in the pipeline routing table. Defaults to the output type.
+    name: Option<String>,
+    /// Only records matching this predicate are sent to the output.
+    when: Option<Predicate>,
+}
+
+impl OutputRoute {
+    /// Extracts routing options from the given output config, removing them from it, so the
+    /// output itself is configured only with its own options.
+    pub fn extract(cfg: &mut Value) -> Result<OutputRoute, Box<Error>> {
+        let mut route = BTreeMap::new();
+
+        if let Some(map) = cfg.as_object_mut() {
+            for key in &["name", "when"] {
+                if let Some(val) = map.remove(*key) {
+                    route.insert((*key).to_owned(), val);
+                }
+            }
+        }
+
+        serde_json::from_value(Value::Object(route)).map_err(Into::into)
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|name| name.as_str())
+    }
+
+    pub fn when(&self) -> Option<&Predicate> {
+        self.when.as_ref()
+    }
+}
+
+/// Record size limits, shared by sources and the truncate filter.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Maximum approximate size of the encoded record in bytes.
+    ///
+    /// Oversized records have their strings shortened and, as the last resort, all fields
+    /// except `message`, `timestamp`, `severity`, `tags` and the marker removed.
+    max_record_size: Option<usize>,
+    /// Maximum length of string values in bytes.
+    max_string_length: Option<usize>,
+    /// Maximum number of array items. Extra items are removed.
+    max_array_length: Option<usize>,
+    /// Maximum nesting depth, counting the record itself. Deeper values are replaced with their
+    /// JSON encoding.
+    max_depth: Option<usize>,
+    /// Field to list pointers of truncated fields in, "_truncated" by default.
+    ///
+    /// Pointers are merged into the existing list, i.e. one left by a source.
+    marker: Option<String>,
+}
+
+impl LimitsConfig {
+    pub fn max_record_size(&self) -> Option<usize> {
+        self.max_record_size
+    }
+
+    pub fn max_string_length(&self) -> Option<usize> {
+        self.max_string_length
+    }
+
+    pub fn max_array_length(&self) -> Option<usize> {
+        self.max_array_length
+    }
+
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    pub fn marker(&self) -> &str {
+        self.marker.as_ref().map(|marker| marker.as_str()).unwrap_or("_truncated")
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Self-report interval in seconds.
+    interval: u64,
+}
+
+impl MetricsConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Address to listen on for metrics scraping, i.e. "127.0.0.1:9100".
+    endpoint: String,
+}
+
+impl HttpConfig {
+    pub fn endpoint(&self) -> Result<SocketAddr, Box<Error>> {
+        self.endpoint.parse()
+            .map_err(|err| format!("invalid HTTP endpoint '{}': {}", self.endpoint, err).into())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Path to the admin Unix socket.
+    path: String,
+}
+
+impl AdminConfig {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// Included config file, that can contain only pipelines.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FragmentConfig {
+    pipelines: Vec<PipeConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct RuntimeConfig {
     /// Logging severity.
-    severity: String,
+    severity: Severity,
     /// Generic pipelines config.
+    #[serde(default)]
     pipelines: Vec<PipeConfig>,
+    /// Files or directories with pipeline fragments to include.
+    ///
+    /// Relative paths are resolved relative to the directory of the main config file. All files
+    /// with `.json` extension are included from directories in lexicographical order.
+    #[serde(default)]
+    include: Vec<String>,
+    /// Optional self-metrics reporting config.
+    metrics: Option<MetricsConfig>,
+    /// Optional embedded HTTP listener config.
+    http: Option<HttpConfig>,
+    /// Optional admin socket config.
+    admin: Option<AdminConfig>,
 }
 
 impl RuntimeConfig {
+    /// Reads the config from the given file, resolving environment variables and file
+    /// references in all string values.
+    ///
+    /// Pipelines from all included fragments are merged into the resulting config. Pipeline
+    /// names must be unique across all of them.
     pub fn from<P: AsRef<Path>>(path: P) -> Result<RuntimeConfig, Box<Error>> {
-        let cfg = try!(serde_json::from_reader(&try!(File::open(path))));
+        let path = path.as_ref();
+        let mut cfg: RuntimeConfig = try!(read(path));
+
+        let mut names = HashSet::new();
+        try!(check_names(&cfg.pipelines, path, &mut names));
+
+        let base = path.parent().unwrap_or(Path::new("."));
+
+        for include in &cfg.include {
+            for path in try!(expand(&base.join(include))) {
+                let fragment: FragmentConfig = try!(read(&path));
+                try!(check_names(&fragment.pipelines, &path, &mut names));
+
+                cfg.pipelines.extend(fragment.pipelines);
+            }
+        }
+
+        for pipe in &mut cfg.pipelines {
+            pipe.base = base.to_path_buf();
+        }
 
         Ok(cfg)
     }
 
-    pub fn severity(&self) -> &str {
-        &self.severity
+    pub fn severity(&self) -> Severity {
+        self.severity
     }
 
     pub fn pipelines(&self) -> &Vec<PipeConfig> {
         &self.pipelines
     }
+
+    pub fn metrics(&self) -> Option<&MetricsConfig> {
+        self.metrics.as_ref()
+    }
+
+    pub fn http(&self) -> Option<&HttpConfig> {
+        self.http.as_ref()
+    }
+
+    pub fn admin(&self) -> Option<&AdminConfig> {
+        self.admin.as_ref()
+    }
+}
+
+/// Reads and decodes the given config file with interpolation.
+fn read<T: Deserialize>(path: &Path) -> Result<T, Box<Error>> {
+    let parse = || -> Result<T, Box<Error>> {
+        let mut value: Value = try!(serde_json::from_reader(&try!(File::open(path))));
+        try!(interpolate::interpolate(&mut value));
+
+        serde_json::from_value(value).map_err(Into::into)
+    };
+
+    parse().map_err(|err| format!("failed to read '{}': {}", path.display(), err).into())
+}
+
+/// Expands an include path into a list of files.
+fn expand(path: &Path) -> Result<Vec<PathBuf>, Box<Error>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut paths = Vec::new();
+
+    for entry in try!(fs::read_dir(path)) {
+        let path = try!(entry).path();
+
+        if path.is_file() && path.extension().map_or(false, |ext| ext == "json") {
+            paths.push(path);
+        }
+    }
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+fn check_names(pipelines: &[PipeConfig], path: &Path, names: &mut HashSet<PipeName>) ->
+    Result<(), Box<Error>>
+{
+    for name in pipelines.iter().filter_map(|pipeline| pipeline.name()) {
+        if !names.insert(name.clone()) {
+            let err = format!("duplicate pipeline name '{}' in '{}'", name, path.display());
+            return Err(err.into());
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,160 @@
+use super::ParseError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Dot,
+}
+
+/// Token with its byte position in the source expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned {
+    pub token: Token,
+    pub pos: usize,
+}
+
+pub fn tokenize(src: &str) -> Result<Vec<Spanned>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+
+    while let Some(&(pos, ch)) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let token = match ch {
+            '(' => { chars.next(); Token::LParen }
+            ')' => { chars.next(); Token::RParen }
+            '[' => { chars.next(); Token::LBracket }
+            ']' => { chars.next(); Token::RBracket }
+            ',' => { chars.next(); Token::Comma }
+            '.' => { chars.next(); Token::Dot }
+            '=' | '!' | '<' | '>' | '&' | '|' => {
+                chars.next();
+                let next = chars.peek().map(|&(_, ch)| ch);
+
+                let (token, double) = match (ch, next) {
+                    ('=', Some('=')) => (Token::Eq, true),
+                    ('=', Some('~')) => (Token::Match, true),
+                    ('!', Some('=')) => (Token::Ne, true),
+                    ('!', Some('~')) => (Token::NotMatch, true),
+                    ('!', _) => (Token::Not, false),
+                    ('<', Some('=')) => (Token::Le, true),
+                    ('<', _) => (Token::Lt, false),
+                    ('>', Some('=')) => (Token::Ge, true),
+                    ('>', _) => (Token::Gt, false),
+                    ('&', Some('&')) => (Token::And, true),
+                    ('|', Some('|')) => (Token::Or, true),
+                    _ => {
+                        return Err(ParseError::new(pos, format!("unexpected character '{}'", ch)));
+                    }
+                };
+
+                if double {
+                    chars.next();
+                }
+
+                token
+            }
+            '"' | '\'' => {
+                chars.next();
+                Token::Str(try!(string(&mut chars, ch, pos)))
+            }
+            '0'...'9' | '-' => {
+                let mut val = String::new();
+                val.push(ch);
+                chars.next();
+
+                while let Some(&(_, ch)) = chars.peek() {
+                    // Exponent may be signed, i.e. `1e-3`.
+                    let exponent = val.ends_with('e') || val.ends_with('E');
+
+                    if ch.is_digit(10) || ch == '.' || ch == 'e' || ch == 'E' ||
+                        (exponent && (ch == '+' || ch == '-'))
+                    {
+                        val.push(ch);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                match val.parse() {
+                    Ok(val) => Token::Num(val),
+                    Err(..) => {
+                        return Err(ParseError::new(pos, format!("invalid number '{}'", val)));
+                    }
+                }
+            }
+            ch if ch.is_alphabetic() || ch == '_' => {
+                let mut val = String::new();
+
+                while let Some(&(_, ch)) = chars.peek() {
+                    if ch.is_alphanumeric() || ch == '_' {
+                        val.push(ch);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                Token::Ident(val)
+            }
+            ch => return Err(ParseError::new(pos, format!("unexpected character '{}'", ch))),
+        };
+
+        tokens.push(Spanned { token: token, pos: pos });
+    }
+
+    Ok(tokens)
+}
+
+/// Reads a quoted string literal, the opening quote has already been consumed.
+///
+/// Unknown escape sequences are preserved as is, which is convenient for regular expressions.
+fn string<I>(chars: &mut I, quote: char, pos: usize) -> Result<String, ParseError>
+    where I: Iterator<Item=(usize, char)>
+{
+    let mut val = String::new();
+
+    loop {
+        match chars.next() {
+            Some((_, ch)) if ch == quote => return Ok(val),
+            Some((_, '\\')) => {
+                match chars.next() {
+                    Some((_, 'n')) => val.push('\n'),
+                    Some((_, 't')) => val.push('\t'),
+                    Some((_, ch)) if ch == quote || ch == '\\' => val.push(ch),
+                    Some((_, ch)) => {
+                        val.push('\\');
+                        val.push(ch);
+                    }
+                    None => break,
+                }
+            }
+            Some((_, ch)) => val.push(ch),
+            None => break,
+        }
+    }
+
+    Err(ParseError::new(pos, "unterminated string literal"))
+}
@@ -0,0 +1,460 @@
+//! Expression language for record predicates.
+//!
+//! Expressions are compiled once, when the config is loaded, and then evaluated against each
+//! record. For example:
+//!
+//! ```text
+//! severity >= WARN && module =~ "^db" && exists(trace_id)
+//! ```
+//!
+//! Supported operands are record fields, including nested ones (`request.headers["x-id"]`,
+//! `tags[0]`), string, number and boolean literals, `null`, and severity constants from `ERROR`
+//! to `TRACE`.
+//!
+//! The special `severity` field is extracted from any of the known severity fields, see
+//! `Severity::from_record`. Severities are compared by their importance, i.e. `ERROR > WARN`.
+//!
+//! Operators, from the lowest precedence: `||`, `&&`, `!`, comparison operators (`==`, `!=`,
+//! `<`, `<=`, `>`, `>=`) and regular expression matching (`=~`, `!~`).
+//!
+//! Functions: `exists(field)`, `len(x)`, `lower(s)`, `upper(s)`, `contains(s, sub)`,
+//! `starts_with(s, prefix)`, `ends_with(s, suffix)`, `int(x)`, `float(x)` and `str(x)`.
+//!
+//! Comparing values of incompatible types, including missing fields with anything but `null`,
+//! results in `false`, except for `!=`, which is always the negation of `==`.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::error;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::iter;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde_json::{self, Value};
+
+use Record;
+use severity::Severity;
+
+use self::parser::{CmpOp, Expr, Func, Literal, Segment};
+
+mod lexer;
+mod parser;
+
+/// Expression parse error with the position in the source expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pos: usize,
+    message: String,
+}
+
+impl ParseError {
+    fn new<T: Into<String>>(pos: usize, message: T) -> ParseError {
+        ParseError {
+            pos: pos,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the byte position in the source expression, where the error occurred.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{} at position {}", self.message, self.pos)
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Compiled boolean expression over records.
+#[derive(Clone)]
+pub struct Predicate {
+    src: String,
+    expr: Expr,
+}
+
+impl Predicate {
+    pub fn new(src: &str) -> Result<Predicate, ParseError> {
+        let predicate = Predicate {
+            src: src.to_owned(),
+            expr: try!(parser::parse(src)),
+        };
+
+        Ok(predicate)
+    }
+
+    /// Returns the source expression.
+    pub fn as_str(&self) -> &str {
+        &self.src
+    }
+
+    /// Returns whether the given record matches this predicate.
+    pub fn matches(&self, record: &Record) -> bool {
+        eval(&self.expr, record).truthy()
+    }
+}
+
+impl Debug for Predicate {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "Predicate({:?})", self.src)
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = ParseError;
+
+    fn from_str(src: &str) -> Result<Predicate, ParseError> {
+        Predicate::new(src)
+    }
+}
+
+impl Deserialize for Predicate {
+    fn deserialize<D>(de: &mut D) -> Result<Predicate, D::Error>
+        where D: Deserializer
+    {
+        let src = try!(String::deserialize(de));
+
+        Predicate::new(&src).map_err(|err| {
+            // Point at the error position, because expressions are usually long enough.
+            de::Error::custom(format!("invalid expression: {}\n  {}\n  {}^",
+                err, src, iter::repeat(' ').take(src[..err.pos].chars().count())
+                    .collect::<String>()))
+        })
+    }
+}
+
+/// Intermediate value of expression evaluation.
+#[derive(Debug, Clone)]
+enum Val<'a> {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(Cow<'a, str>),
+    Sev(Severity),
+    /// Either array or object.
+    Json(&'a Value),
+}
+
+impl<'a> Val<'a> {
+    fn from_json(val: &'a Value) -> Val<'a> {
+        match *val {
+            Value::Null => Val::Null,
+            Value::Bool(val) => Val::Bool(val),
+            Value::I64(val) => Val::Num(val as f64),
+            Value::U64(val) => Val::Num(val as f64),
+            Value::F64(val) => Val::Num(val),
+            Value::String(ref val) => Val::Str(Cow::Borrowed(val)),
+            Value::Array(..) | Value::Object(..) => Val::Json(val),
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        match *self {
+            Val::Null => false,
+            Val::Bool(val) => val,
+            Val::Num(val) => val != 0.0,
+            Val::Str(ref val) => !val.is_empty(),
+            Val::Sev(..) | Val::Json(..) => true,
+        }
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        match *self {
+            Val::Sev(sev) => Some(sev),
+            Val::Str(ref val) => Severity::from_name(val),
+            Val::Num(val) if val >= 0.0 && val.fract() == 0.0 => {
+                Severity::from_usize(val as usize)
+            }
+            _ => None,
+        }
+    }
+
+    fn to_string(&self) -> Option<String> {
+        match *self {
+            Val::Null => None,
+            Val::Bool(val) => Some(val.to_string()),
+            Val::Num(val) if val.fract() == 0.0 && val.abs() < 1e15 => {
+                Some((val as i64).to_string())
+            }
+            Val::Num(val) => Some(val.to_string()),
+            Val::Str(ref val) => Some(val.clone().into_owned()),
+            Val::Sev(sev) => Some(sev.as_str().to_owned()),
+            Val::Json(val) => serde_json::to_string(val).ok(),
+        }
+    }
+
+    fn to_number(&self) -> Option<f64> {
+        match *self {
+            Val::Bool(val) => Some(if val { 1.0 } else { 0.0 }),
+            Val::Num(val) => Some(val),
+            Val::Str(ref val) => val.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Importance of the given severity, the more severe, the greater.
+fn rank(sev: Severity) -> usize {
+    Severity::Trace.as_usize() - sev.as_usize()
+}
+
+fn compare(lhs: &Val, rhs: &Val) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (&Val::Sev(..), _) | (_, &Val::Sev(..)) => {
+            match (lhs.severity(), rhs.severity()) {
+                (Some(lhs), Some(rhs)) => Some(rank(lhs).cmp(&rank(rhs))),
+                _ => None,
+            }
+        }
+        (&Val::Num(lhs), &Val::Num(rhs)) => lhs.partial_cmp(&rhs),
+        (&Val::Str(ref lhs), &Val::Str(ref rhs)) => Some(lhs.cmp(rhs)),
+        (&Val::Bool(lhs), &Val::Bool(rhs)) => Some(lhs.cmp(&rhs)),
+        (&Val::Null, &Val::Null) => Some(Ordering::Equal),
+        (&Val::Json(lhs), &Val::Json(rhs)) if lhs == rhs => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+fn lookup<'a>(record: &'a Record, path: &[Segment]) -> Option<&'a Value> {
+    let mut val = record;
+
+    for segment in path {
+        let next = match *segment {
+            Segment::Key(ref key) => val.find(key),
+            Segment::Index(id) => val.as_array().and_then(|vec| vec.get(id)),
+        };
+
+        val = match next {
+            Some(next) => next,
+            None => return None,
+        };
+    }
+
+    Some(val)
+}
+
+fn eval<'a>(expr: &'a Expr, record: &'a Record) -> Val<'a> {
+    match *expr {
+        Expr::Lit(ref lit) => {
+            match *lit {
+                Literal::Null => Val::Null,
+                Literal::Bool(val) => Val::Bool(val),
+                Literal::Num(val) => Val::Num(val),
+                Literal::Str(ref val) => Val::Str(Cow::Borrowed(val)),
+                Literal::Sev(sev) => Val::Sev(sev),
+            }
+        }
+        Expr::Path(ref path) => lookup(record, path).map_or(Val::Null, Val::from_json),
+        Expr::Severity => Severity::from_record(record).map_or(Val::Null, Val::Sev),
+        Expr::Exists(ref expr) => {
+            let exists = match **expr {
+                Expr::Path(ref path) => lookup(record, path).is_some(),
+                Expr::Severity => Severity::from_record(record).is_some(),
+                _ => false,
+            };
+
+            Val::Bool(exists)
+        }
+        Expr::Not(ref expr) => Val::Bool(!eval(expr, record).truthy()),
+        Expr::And(ref lhs, ref rhs) => {
+            Val::Bool(eval(lhs, record).truthy() && eval(rhs, record).truthy())
+        }
+        Expr::Or(ref lhs, ref rhs) => {
+            Val::Bool(eval(lhs, record).truthy() || eval(rhs, record).truthy())
+        }
+        Expr::Cmp(op, ref lhs, ref rhs) => {
+            let ord = compare(&eval(lhs, record), &eval(rhs, record));
+
+            let result = match op {
+                CmpOp::Eq => ord == Some(Ordering::Equal),
+                CmpOp::Ne => ord != Some(Ordering::Equal),
+                CmpOp::Lt => ord == Some(Ordering::Less),
+                CmpOp::Le => ord == Some(Ordering::Less) || ord == Some(Ordering::Equal),
+                CmpOp::Gt => ord == Some(Ordering::Greater),
+                CmpOp::Ge => ord == Some(Ordering::Greater) || ord == Some(Ordering::Equal),
+            };
+
+            Val::Bool(result)
+        }
+        Expr::Match(ref expr, ref regex, negate) => {
+            let matched = match eval(expr, record) {
+                Val::Str(ref val) => regex.is_match(val),
+                Val::Null => false,
+                val => val.to_string().map_or(false, |val| regex.is_match(&val)),
+            };
+
+            Val::Bool(matched != negate)
+        }
+        Expr::Call(func, ref args) => call(func, args, record),
+    }
+}
+
+fn call<'a>(func: Func, args: &'a [Expr], record: &'a Record) -> Val<'a> {
+    let arg = eval(&args[0], record);
+
+    match func {
+        Func::Len => {
+            match arg {
+                Val::Str(ref val) => Val::Num(val.chars().count() as f64),
+                Val::Json(&Value::Array(ref vec)) => Val::Num(vec.len() as f64),
+                Val::Json(&Value::Object(ref map)) => Val::Num(map.len() as f64),
+                _ => Val::Null,
+            }
+        }
+        Func::Lower => {
+            match arg {
+                Val::Str(ref val) => Val::Str(Cow::Owned(val.to_lowercase())),
+                _ => Val::Null,
+            }
+        }
+        Func::Upper => {
+            match arg {
+                Val::Str(ref val) => Val::Str(Cow::Owned(val.to_uppercase())),
+                _ => Val::Null,
+            }
+        }
+        Func::Contains => {
+            let needle = eval(&args[1], record);
+
+            match arg {
+                Val::Str(ref val) => {
+                    match needle {
+                        Val::Str(ref needle) => Val::Bool(val.contains(&needle[..])),
+                        _ => Val::Bool(false),
+                    }
+                }
+                Val::Json(&Value::Array(ref vec)) => {
+                    let found = vec.iter().any(|val| {
+                        compare(&Val::from_json(val), &needle) == Some(Ordering::Equal)
+                    });
+
+                    Val::Bool(found)
+                }
+                _ => Val::Bool(false),
+            }
+        }
+        Func::StartsWith | Func::EndsWith => {
+            match (arg, eval(&args[1], record)) {
+                (Val::Str(ref val), Val::Str(ref affix)) => {
+                    if func == Func::StartsWith {
+                        Val::Bool(val.starts_with(&affix[..]))
+                    } else {
+                        Val::Bool(val.ends_with(&affix[..]))
+                    }
+                }
+                _ => Val::Bool(false),
+            }
+        }
+        Func::Int => arg.to_number().map_or(Val::Null, |val| Val::Num(val.trunc())),
+        Func::Float => arg.to_number().map_or(Val::Null, Val::Num),
+        Func::Str => arg.to_string().map_or(Val::Null, |val| Val::Str(Cow::Owned(val))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{self, Value};
+
+    use super::Predicate;
+    use super::lexer::{tokenize, Token};
+
+    fn matches(src: &str, record: &str) -> bool {
+        let record: Value = serde_json::from_str(record).unwrap();
+        Predicate::new(src).unwrap().matches(&record)
+    }
+
+    #[test]
+    fn precedence() {
+        assert!(matches("true || false && false", "{}"));
+        assert!(!matches("(true || false) && false", "{}"));
+        assert!(!matches("!false && false", "{}"));
+        assert!(matches("!a == 1", r#"{"a": 2}"#));
+        assert!(matches("a == 1 && b == 2 || c == 3", r#"{"a": 0, "c": 3}"#));
+    }
+
+    #[test]
+    fn severity_comparison() {
+        let record = r#"{"levelname": "WARNING"}"#;
+
+        assert!(matches("severity >= WARN", record));
+        assert!(!matches("severity > WARN", record));
+        assert!(matches("severity < ERROR", record));
+        assert!(matches("severity > INFO", record));
+        assert!(matches("severity == \"warn\"", record));
+
+        assert!(matches("severity == ERROR", r#"{"severity": 0}"#));
+        assert!(matches("severity == DEBUG", r#"{"levelno": 10}"#));
+        assert!(!matches("severity >= TRACE", "{}"));
+    }
+
+    #[test]
+    fn regex_operators() {
+        let record = r#"{"module": "db.pool", "code": 503}"#;
+
+        assert!(matches("module =~ \"^db\\.\"", record));
+        assert!(!matches("module !~ \"^db\"", record));
+        assert!(matches("code =~ \"^5\"", record));
+        assert!(!matches("missing =~ \".*\"", record));
+        assert!(matches("missing !~ \".*\"", record));
+    }
+
+    #[test]
+    fn missing_fields() {
+        let record = r#"{"a": {"b": [1, 2]}}"#;
+
+        assert!(matches("a.b[1] == 2", record));
+        assert!(matches("missing == null", record));
+        assert!(!matches("missing == 1", record));
+        assert!(!matches("missing > 1", record));
+        assert!(!matches("missing < 1", record));
+        assert!(matches("missing != 1", record));
+        assert!(!matches("exists(missing)", record));
+        assert!(matches("exists(a.b)", record));
+        assert!(matches("!missing", record));
+        assert!(!matches("a.b[5] == 1", record));
+    }
+
+    #[test]
+    fn incompatible_types() {
+        assert!(!matches("a == \"1\"", r#"{"a": true}"#));
+        assert!(matches("a != \"1\"", r#"{"a": true}"#));
+        assert!(!matches("a < \"b\"", r#"{"a": 1}"#));
+    }
+
+    #[test]
+    fn lexer_numbers() {
+        let tokens: Vec<Token> = tokenize("1 -2 1.5 1e3 1e-3 1.5E+2").unwrap()
+            .into_iter()
+            .map(|spanned| spanned.token)
+            .collect();
+
+        assert_eq!(vec![Token::Num(1.0), Token::Num(-2.0), Token::Num(1.5), Token::Num(1000.0),
+            Token::Num(0.001), Token::Num(150.0)], tokens);
+    }
+
+    #[test]
+    fn lexer_errors() {
+        assert_eq!(5, tokenize("a == #").unwrap_err().pos());
+        assert_eq!(2, tokenize("a = b").unwrap_err().pos());
+        assert!(tokenize("1e").is_err());
+        assert!(tokenize("1.2.3").is_err());
+        assert!(tokenize("\"unterminated").is_err());
+    }
+
+    #[test]
+    fn parser_errors() {
+        assert!(Predicate::new("").is_err());
+        assert!(Predicate::new("(a == 1").is_err());
+        assert!(Predicate::new("a ==").is_err());
+        assert!(Predicate::new("unknown(a)").is_err());
+        assert!(Predicate::new("a =~ \"(\"").is_err());
+    }
+}
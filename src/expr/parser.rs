@@ -0,0 +1,334 @@
+use regex::Regex;
+
+use severity::Severity;
+
+use super::ParseError;
+use super::lexer::{self, Spanned, Token};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Sev(Severity),
+}
+
+/// Path segment for nested field access.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Func {
+    Len,
+    Lower,
+    Upper,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Int,
+    Float,
+    Str,
+}
+
+impl Func {
+    fn from_name(name: &str) -> Option<(Func, usize)> {
+        let func = match name {
+            "len" => (Func::Len, 1),
+            "lower" => (Func::Lower, 1),
+            "upper" => (Func::Upper, 1),
+            "contains" => (Func::Contains, 2),
+            "starts_with" => (Func::StartsWith, 2),
+            "ends_with" => (Func::EndsWith, 2),
+            "int" => (Func::Int, 1),
+            "float" => (Func::Float, 1),
+            "str" => (Func::Str, 1),
+            _ => return None,
+        };
+
+        Some(func)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Lit(Literal),
+    /// Record field, possibly nested.
+    Path(Vec<Segment>),
+    /// Record severity, extracted from any of the known severity fields.
+    Severity,
+    Exists(Box<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Cmp(CmpOp, Box<Expr>, Box<Expr>),
+    /// Regular expression match, possibly negated.
+    Match(Box<Expr>, Regex, bool),
+    Call(Func, Vec<Expr>),
+}
+
+/// Recursive descent parser.
+///
+/// ```text
+/// or      := and ('||' and)*
+/// and     := not ('&&' not)*
+/// not     := '!' not | cmp
+/// cmp     := primary (('==' | '!=' | '<' | '<=' | '>' | '>=') primary | ('=~' | '!~') STRING)?
+/// primary := NUMBER | STRING | IDENT '(' args ')' | path | '(' or ')'
+/// path    := IDENT ('.' IDENT | '[' (NUMBER | STRING) ']')*
+/// ```
+struct Parser<'a> {
+    src: &'a str,
+    tokens: Vec<Spanned>,
+    id: usize,
+}
+
+pub fn parse(src: &str) -> Result<Expr, ParseError> {
+    let mut parser = Parser {
+        src: src,
+        tokens: try!(lexer::tokenize(src)),
+        id: 0,
+    };
+
+    let expr = try!(parser.or());
+
+    match parser.peek() {
+        Some(..) => Err(parser.error("unexpected token, expected end of expression")),
+        None => Ok(expr),
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.id).map(|spanned| &spanned.token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.id).map(|spanned| spanned.token.clone());
+        self.id += 1;
+        token
+    }
+
+    /// Returns the position of the current token, or the end of the expression.
+    fn pos(&self) -> usize {
+        self.tokens.get(self.id)
+            .map(|spanned| spanned.pos)
+            .unwrap_or(self.src.len())
+    }
+
+    fn error<T: Into<String>>(&self, message: T) -> ParseError {
+        ParseError::new(self.pos(), message)
+    }
+
+    fn expect(&mut self, token: Token, message: &str) -> Result<(), ParseError> {
+        if self.peek() == Some(&token) {
+            self.id += 1;
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = try!(self.and());
+
+        while self.peek() == Some(&Token::Or) {
+            self.id += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(try!(self.and())));
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = try!(self.not());
+
+        while self.peek() == Some(&Token::And) {
+            self.id += 1;
+            expr = Expr::And(Box::new(expr), Box::new(try!(self.not())));
+        }
+
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, ParseError> {
+        if self.peek() == Some(&Token::Not) {
+            self.id += 1;
+            return Ok(Expr::Not(Box::new(try!(self.not()))));
+        }
+
+        self.cmp()
+    }
+
+    fn cmp(&mut self) -> Result<Expr, ParseError> {
+        let lhs = try!(self.primary());
+
+        let op = match self.peek() {
+            Some(&Token::Eq) => CmpOp::Eq,
+            Some(&Token::Ne) => CmpOp::Ne,
+            Some(&Token::Lt) => CmpOp::Lt,
+            Some(&Token::Le) => CmpOp::Le,
+            Some(&Token::Gt) => CmpOp::Gt,
+            Some(&Token::Ge) => CmpOp::Ge,
+            Some(&Token::Match) | Some(&Token::NotMatch) => {
+                let negate = self.next() == Some(Token::NotMatch);
+                let pos = self.pos();
+
+                let pattern = match self.next() {
+                    Some(Token::Str(pattern)) => pattern,
+                    _ => return Err(ParseError::new(pos, "expected regular expression string")),
+                };
+
+                let regex = try!(Regex::new(&pattern)
+                    .map_err(|err| ParseError::new(pos, format!("invalid regex: {}", err))));
+
+                return Ok(Expr::Match(Box::new(lhs), regex, negate));
+            }
+            _ => return Ok(lhs),
+        };
+
+        self.id += 1;
+        let rhs = try!(self.primary());
+
+        Ok(Expr::Cmp(op, Box::new(lhs), Box::new(rhs)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let pos = self.pos();
+
+        match self.next() {
+            Some(Token::Num(val)) => Ok(Expr::Lit(Literal::Num(val))),
+            Some(Token::Str(val)) => Ok(Expr::Lit(Literal::Str(val))),
+            Some(Token::LParen) => {
+                let expr = try!(self.or());
+                try!(self.expect(Token::RParen, "expected ')'"));
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => {
+                if self.peek() == Some(&Token::LParen) {
+                    self.id += 1;
+                    return self.call(&name, pos);
+                }
+
+                self.ident(name)
+            }
+            Some(..) => Err(ParseError::new(pos, "unexpected token, expected operand")),
+            None => Err(ParseError::new(pos, "unexpected end of expression, expected operand")),
+        }
+    }
+
+    fn ident(&mut self, name: String) -> Result<Expr, ParseError> {
+        let nested = match self.peek() {
+            Some(&Token::Dot) | Some(&Token::LBracket) => true,
+            _ => false,
+        };
+
+        if !nested {
+            let lit = match &name[..] {
+                "true" => Some(Literal::Bool(true)),
+                "false" => Some(Literal::Bool(false)),
+                "null" => Some(Literal::Null),
+                "ERROR" => Some(Literal::Sev(Severity::Error)),
+                "WARN" => Some(Literal::Sev(Severity::Warn)),
+                "INFO" => Some(Literal::Sev(Severity::Info)),
+                "DEBUG" => Some(Literal::Sev(Severity::Debug)),
+                "TRACE" => Some(Literal::Sev(Severity::Trace)),
+                _ => None,
+            };
+
+            if let Some(lit) = lit {
+                return Ok(Expr::Lit(lit));
+            }
+
+            if name == "severity" {
+                return Ok(Expr::Severity);
+            }
+        }
+
+        let mut path = vec![Segment::Key(name)];
+
+        loop {
+            match self.peek() {
+                Some(&Token::Dot) => {
+                    self.id += 1;
+                    let pos = self.pos();
+
+                    match self.next() {
+                        Some(Token::Ident(key)) => path.push(Segment::Key(key)),
+                        _ => return Err(ParseError::new(pos, "expected field name")),
+                    }
+                }
+                Some(&Token::LBracket) => {
+                    self.id += 1;
+                    let pos = self.pos();
+
+                    match self.next() {
+                        Some(Token::Str(key)) => path.push(Segment::Key(key)),
+                        Some(Token::Num(id)) if id >= 0.0 && id.fract() == 0.0 => {
+                            path.push(Segment::Index(id as usize));
+                        }
+                        _ => return Err(ParseError::new(pos, "expected field name or index")),
+                    }
+
+                    try!(self.expect(Token::RBracket, "expected ']'"));
+                }
+                _ => break,
+            }
+        }
+
+        Ok(Expr::Path(path))
+    }
+
+    fn call(&mut self, name: &str, pos: usize) -> Result<Expr, ParseError> {
+        let mut args = Vec::new();
+
+        if self.peek() != Some(&Token::RParen) {
+            loop {
+                args.push(try!(self.or()));
+
+                if self.peek() == Some(&Token::Comma) {
+                    self.id += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+
+        try!(self.expect(Token::RParen, "expected ')' or ','"));
+
+        if name == "exists" {
+            if args.len() != 1 {
+                return Err(ParseError::new(pos, "function 'exists' expects a single field"));
+            }
+
+            return match args.pop().unwrap() {
+                arg @ Expr::Path(..) | arg @ Expr::Severity => Ok(Expr::Exists(Box::new(arg))),
+                _ => Err(ParseError::new(pos, "function 'exists' expects a single field")),
+            };
+        }
+
+        let (func, arity) = try!(Func::from_name(name)
+            .ok_or(ParseError::new(pos, format!("unknown function '{}'", name))));
+
+        if args.len() != arity {
+            let message = format!("function '{}' expects {} argument(s), {} given",
+                name, arity, args.len());
+            return Err(ParseError::new(pos, message));
+        }
+
+        Ok(Expr::Call(func, args))
+    }
+}
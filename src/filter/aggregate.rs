@@ -0,0 +1,261 @@
+use std::cmp::{self, Ordering};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::f64;
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{self, Value};
+
+use {Config, Record};
+use config;
+use filter::{self, Filter, FilterFactory};
+use filter::config::AggregateConfig;
+use filter::pointer::Pointer;
+use metrics::{Counter, Scope};
+use severity::Severity;
+
+/// Returns the value at the given percentile of sorted values, using the nearest-rank method.
+fn percentile(sorted: &[f64], rank: f64) -> f64 {
+    let id = (rank / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[cmp::min(id.saturating_sub(1), sorted.len() - 1)]
+}
+
+/// Returns the summary field name for the given percentile, i.e. "p99" or "p99.9".
+fn percentile_name(rank: f64) -> String {
+    if rank.fract() == 0.0 {
+        format!("p{}", rank as u64)
+    } else {
+        format!("p{}", rank)
+    }
+}
+
+/// Statistics of a single group within the current window.
+struct Group {
+    /// Key field values, used for summary records.
+    key: Vec<Value>,
+    count: u64,
+    /// Number of records with numeric field values.
+    values: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    /// Uniform sample of field values for percentile estimation.
+    samples: Vec<f64>,
+}
+
+/// Groups records by the given fields over tumbling windows, emitting a summary record per group
+/// at the end of each window.
+///
+/// Summaries include the window bounds, the number of records and, if configured, min, max,
+/// average and percentiles of a numeric field. The current partial window is emitted on pipeline
+/// shutdown.
+pub struct AggregateFilter {
+    key: Vec<Pointer>,
+    field: Option<Pointer>,
+    window: Duration,
+    /// Start of the current window, used for scheduling.
+    started: Instant,
+    /// Start of the current window in nanoseconds since the epoch, used for summaries.
+    start: i64,
+    percentiles: Vec<f64>,
+    max_samples: usize,
+    max_keys: usize,
+    pass: bool,
+    groups: BTreeMap<String, Group>,
+    /// Xorshift random generator state for sampling, which is never zero.
+    state: u64,
+    overflows: Counter,
+}
+
+impl AggregateFilter {
+    fn new(cfg: AggregateConfig, metrics: &Scope) -> Result<AggregateFilter, Box<Error>> {
+        if cfg.window().as_secs() == 0 {
+            return Err("window must be positive".into());
+        }
+
+        let percentiles = cfg.percentiles();
+        if let Some(rank) = percentiles.iter().find(|&&rank| rank <= 0.0 || rank > 100.0) {
+            return Err(format!("percentiles must be in (0; 100] range, got {}", rank).into());
+        }
+
+        if cfg.max_samples() == 0 {
+            return Err("max_samples must be positive".into());
+        }
+
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|val| val.as_secs() ^ val.subsec_nanos() as u64)
+            .unwrap_or(0);
+
+        let filter = AggregateFilter {
+            key: cfg.key().clone(),
+            field: cfg.field().cloned(),
+            window: cfg.window(),
+            started: Instant::now(),
+            start: filter::timestamp(),
+            percentiles: percentiles,
+            max_samples: cfg.max_samples(),
+            max_keys: cfg.max_keys(),
+            pass: cfg.pass(),
+            groups: BTreeMap::new(),
+            state: seed | 1,
+            overflows: metrics.counter("zenlog_filter_overflows_total",
+                "Number of records not aggregated because of the groups limit"),
+        };
+
+        Ok(filter)
+    }
+
+    fn random(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        self.state.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    fn summarize(&self, group: &mut Group, end: i64) -> Record {
+        let mut key = BTreeMap::new();
+        for (path, val) in self.key.iter().zip(group.key.iter()) {
+            key.insert(path.to_string().trim_left_matches('/').to_owned(), val.clone());
+        }
+
+        let mut aggregate = BTreeMap::new();
+        aggregate.insert("key".to_owned(), Value::Object(key));
+        // The partial window, emitted on shutdown, is shorter than configured.
+        let window = (end - self.start) as f64 / 1e9;
+
+        aggregate.insert("window".to_owned(), Value::F64(window));
+        aggregate.insert("window_start".to_owned(), Value::I64(self.start));
+        aggregate.insert("window_end".to_owned(), Value::I64(end));
+        aggregate.insert("count".to_owned(), Value::U64(group.count));
+
+        if let Some(ref field) = self.field {
+            aggregate.insert("field".to_owned(), Value::String(field.to_string()));
+
+            if group.values > 0 {
+                aggregate.insert("sum".to_owned(), Value::F64(group.sum));
+                aggregate.insert("min".to_owned(), Value::F64(group.min));
+                aggregate.insert("max".to_owned(), Value::F64(group.max));
+                aggregate.insert("avg".to_owned(), Value::F64(group.sum / group.values as f64));
+
+                group.samples.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+                for &rank in &self.percentiles {
+                    aggregate.insert(percentile_name(rank),
+                        Value::F64(percentile(&group.samples, rank)));
+                }
+            }
+        }
+
+        let mut record = BTreeMap::new();
+        record.insert("message".to_owned(), Value::String(format!(
+            "aggregated {} record(s) in the last {}s", group.count, window)));
+        record.insert("severity".to_owned(), Value::String(Severity::Info.as_str().to_owned()));
+        record.insert("timestamp".to_owned(), Value::I64(filter::timestamp()));
+        record.insert("aggregate".to_owned(), Value::Object(aggregate));
+
+        Value::Object(record)
+    }
+
+    /// Emits summaries of all groups for the window ending at the given time.
+    fn emit(&mut self, end: i64, out: &mut Vec<Arc<Record>>) {
+        let groups = mem::replace(&mut self.groups, BTreeMap::new());
+
+        for (_, mut group) in groups {
+            out.push(Arc::new(self.summarize(&mut group, end)));
+        }
+    }
+}
+
+impl Filter for AggregateFilter {
+    fn filter(&mut self, record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        let value = self.field.as_ref()
+            .and_then(|field| field.find(&record))
+            .and_then(|val| val.as_f64());
+
+        let key: Vec<Value> = self.key.iter()
+            .map(|path| path.find(&record).cloned().unwrap_or(Value::Null))
+            .collect();
+        let id = serde_json::to_string(&key).unwrap_or_else(|_| String::new());
+
+        if self.pass {
+            out.push(record);
+        }
+
+        // Protect from unbounded memory growth on high-cardinality keys.
+        if !self.groups.contains_key(&id) && self.groups.len() >= self.max_keys {
+            self.overflows.inc();
+            return;
+        }
+
+        let random = self.random();
+        let max_samples = self.max_samples;
+
+        let group = self.groups.entry(id).or_insert_with(move || {
+            Group {
+                key: key,
+                count: 0,
+                values: 0,
+                sum: 0.0,
+                min: f64::INFINITY,
+                max: f64::NEG_INFINITY,
+                samples: Vec::new(),
+            }
+        });
+
+        group.count += 1;
+
+        if let Some(value) = value {
+            group.values += 1;
+            group.sum += value;
+            group.min = group.min.min(value);
+            group.max = group.max.max(value);
+
+            // Reservoir sampling keeps each value with equal probability.
+            if group.samples.len() < max_samples {
+                group.samples.push(value);
+            } else {
+                let id = (random % group.values) as usize;
+                if id < max_samples {
+                    group.samples[id] = value;
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Arc<Record>>) {
+        // Windows are advanced by their length rather than restarted, so late ticks never make
+        // them drift.
+        while now.duration_since(self.started) >= self.window {
+            let end = self.start + self.window.as_secs() as i64 * 1000000000;
+            self.emit(end, out);
+
+            self.started += self.window;
+            self.start = end;
+        }
+    }
+
+    fn flush(&mut self, out: &mut Vec<Arc<Record>>) {
+        let end = filter::timestamp();
+        self.emit(end, out);
+    }
+}
+
+impl FilterFactory for AggregateFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "aggregate"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: AggregateConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(try!(AggregateFilter::new(cfg, metrics))))
+    }
+}
@@ -0,0 +1,911 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde_json::Value;
+
+use filter::pointer::Pointer;
+use severity::Severity;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeverityConfig {
+    /// Records less severe than this are dropped.
+    threshold: Severity,
+    /// Per-module thresholds, keyed by the `module` field value.
+    #[serde(default)]
+    modules: BTreeMap<String, Severity>,
+}
+
+impl SeverityConfig {
+    pub fn threshold(&self) -> Severity {
+        self.threshold
+    }
+
+    pub fn modules(&self) -> &BTreeMap<String, Severity> {
+        &self.modules
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostnameConfig {
+    /// Field to store the hostname in, "hostname" by default.
+    field: Option<String>,
+    /// Whether to overwrite the existing field, true by default.
+    overwrite: Option<bool>,
+}
+
+impl HostnameConfig {
+    pub fn field(&self) -> &str {
+        self.field.as_ref().map(|field| field.as_str()).unwrap_or("hostname")
+    }
+
+    pub fn overwrite(&self) -> bool {
+        self.overwrite.unwrap_or(true)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldsConfig {
+    /// Static fields.
+    #[serde(default)]
+    fields: BTreeMap<String, Value>,
+    /// Fields filled with environment variable values, keyed by field name.
+    ///
+    /// Variables are read once, when the filter is constructed. Undefined variables are errors.
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    /// Field to store the pipeline name in.
+    pipeline: Option<String>,
+    /// Whether to overwrite existing fields, true by default.
+    overwrite: Option<bool>,
+}
+
+impl FieldsConfig {
+    pub fn fields(&self) -> &BTreeMap<String, Value> {
+        &self.fields
+    }
+
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    pub fn pipeline(&self) -> Option<&str> {
+        self.pipeline.as_ref().map(|field| field.as_str())
+    }
+
+    pub fn overwrite(&self) -> bool {
+        self.overwrite.unwrap_or(true)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    /// Shorthand for `keep`.
+    #[serde(rename="/fields")]
+    fields: Option<Vec<Pointer>>,
+    /// Fields to keep, all others are removed.
+    keep: Option<Vec<Pointer>>,
+    /// Fields to remove.
+    #[serde(default)]
+    drop: Vec<Pointer>,
+    /// Fields to rename in place, mapping paths to new names.
+    ///
+    /// Renames and moves are independent of each other, so chains like `a -> b`, `b -> c` are
+    /// rejected. Write `a -> c` instead.
+    #[serde(default)]
+    rename: BTreeMap<String, String>,
+    /// Fields to move, mapping source paths to destination paths.
+    ///
+    /// Fields are left in place if some field on the destination path is not an object.
+    #[serde(default)]
+    #[serde(rename="move")]
+    moves: BTreeMap<String, String>,
+}
+
+impl RouteConfig {
+    pub fn keep(&self) -> Result<Option<&Vec<Pointer>>, String> {
+        match (self.fields.as_ref(), self.keep.as_ref()) {
+            (Some(..), Some(..)) => Err("'/fields' and 'keep' are mutually exclusive".into()),
+            (fields, keep) => Ok(fields.or(keep)),
+        }
+    }
+
+    pub fn drop(&self) -> &Vec<Pointer> {
+        &self.drop
+    }
+
+    pub fn rename(&self) -> &BTreeMap<String, String> {
+        &self.rename
+    }
+
+    pub fn moves(&self) -> &BTreeMap<String, String> {
+        &self.moves
+    }
+}
+
+/// Describes what to do with records, that do not match any pattern.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnMismatch {
+    /// Pass the record unchanged.
+    Keep,
+    /// Drop the record.
+    Drop,
+    /// Pass the record, appending a failure tag to its `tags` array.
+    Tag,
+}
+
+impl FromStr for OnMismatch {
+    type Err = String;
+
+    fn from_str(val: &str) -> Result<OnMismatch, String> {
+        match val {
+            "keep" => Ok(OnMismatch::Keep),
+            "drop" => Ok(OnMismatch::Drop),
+            "tag" => Ok(OnMismatch::Tag),
+            _ => {
+                Err(format!("invalid mismatch policy '{}', must be one of 'keep', 'drop' or 'tag'",
+                    val))
+            }
+        }
+    }
+}
+
+impl Deserialize for OnMismatch {
+    fn deserialize<D>(de: &mut D) -> Result<OnMismatch, D::Error>
+        where D: Deserializer
+    {
+        let val = try!(String::deserialize(de));
+        val.parse().map_err(de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GrokConfig {
+    /// Field to parse, "message" by default.
+    field: Option<Pointer>,
+    /// Patterns to try in order until the first match.
+    ///
+    /// Note that config interpolation applies, so a literal `$$` must be written as `$$$$`.
+    patterns: Vec<String>,
+    /// Additional named patterns, that can be referenced from other patterns.
+    #[serde(default)]
+    definitions: BTreeMap<String, String>,
+    on_mismatch: Option<OnMismatch>,
+    /// Whether to overwrite existing fields with captures, true by default.
+    overwrite: Option<bool>,
+}
+
+impl GrokConfig {
+    pub fn field(&self) -> Pointer {
+        self.field.clone().unwrap_or_else(|| "message".parse().unwrap())
+    }
+
+    pub fn patterns(&self) -> &Vec<String> {
+        &self.patterns
+    }
+
+    pub fn definitions(&self) -> &BTreeMap<String, String> {
+        &self.definitions
+    }
+
+    pub fn on_mismatch(&self) -> OnMismatch {
+        self.on_mismatch.unwrap_or(OnMismatch::Keep)
+    }
+
+    pub fn overwrite(&self) -> bool {
+        self.overwrite.unwrap_or(true)
+    }
+}
+
+/// Embedded document format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// JSON if the value looks like containing a JSON object, logfmt otherwise. Logfmt is only
+    /// recognized if every token is a `key=value` pair.
+    Auto,
+    Json,
+    Logfmt,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(val: &str) -> Result<Format, String> {
+        match val {
+            "auto" => Ok(Format::Auto),
+            "json" => Ok(Format::Json),
+            "logfmt" => Ok(Format::Logfmt),
+            _ => {
+                Err(format!("invalid format '{}', must be one of 'auto', 'json' or 'logfmt'", val))
+            }
+        }
+    }
+}
+
+impl Deserialize for Format {
+    fn deserialize<D>(de: &mut D) -> Result<Format, D::Error>
+        where D: Deserializer
+    {
+        let val = try!(String::deserialize(de));
+        val.parse().map_err(de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DecodeConfig {
+    /// Field to decode, "message" by default.
+    field: Option<Pointer>,
+    format: Option<Format>,
+    /// Field to store the decoded object in. Decoded fields are merged into the record root if
+    /// omitted.
+    target: Option<Pointer>,
+    /// Whether to remove the source field after successful decoding, false by default.
+    ///
+    /// The field is kept if none of the decoded data has been merged, i.e. because of
+    /// `overwrite`.
+    remove: Option<bool>,
+    on_mismatch: Option<OnMismatch>,
+    /// Whether to overwrite existing fields, true by default. The source field is never
+    /// overwritten while merging into the record root.
+    overwrite: Option<bool>,
+}
+
+impl DecodeConfig {
+    pub fn field(&self) -> Pointer {
+        self.field.clone().unwrap_or_else(|| "message".parse().unwrap())
+    }
+
+    pub fn format(&self) -> Format {
+        self.format.unwrap_or(Format::Auto)
+    }
+
+    pub fn target(&self) -> Option<&Pointer> {
+        self.target.as_ref()
+    }
+
+    pub fn remove(&self) -> bool {
+        self.remove.unwrap_or(false)
+    }
+
+    pub fn on_mismatch(&self) -> OnMismatch {
+        self.on_mismatch.unwrap_or(OnMismatch::Keep)
+    }
+
+    pub fn overwrite(&self) -> bool {
+        self.overwrite.unwrap_or(true)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Fields, which values identify a bucket. All records share the same bucket if empty.
+    #[serde(default)]
+    key: Vec<Pointer>,
+    /// Number of records per second allowed for each bucket.
+    rate: f64,
+    /// Maximum burst size, equals to the rate by default.
+    burst: Option<f64>,
+    /// Interval in seconds between summary records about suppressed records, 60 by default.
+    ///
+    /// Zero disables summary records.
+    summary: Option<u64>,
+    /// Maximum number of tracked buckets, 10000 by default.
+    ///
+    /// Records with new keys are passed through when the limit is reached.
+    max_keys: Option<usize>,
+}
+
+impl RateLimitConfig {
+    pub fn key(&self) -> &Vec<Pointer> {
+        &self.key
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn burst(&self) -> f64 {
+        self.burst.unwrap_or(self.rate)
+    }
+
+    pub fn summary(&self) -> Option<Duration> {
+        match self.summary.unwrap_or(60) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    pub fn max_keys(&self) -> usize {
+        self.max_keys.unwrap_or(10000)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SampleConfig {
+    /// Fraction of records to keep, in (0; 1] range.
+    rate: f64,
+    /// Field, which value hash makes the sampling decision, i.e. "trace_id".
+    ///
+    /// All records with the same value are either kept or dropped together. Records without
+    /// this field are sampled randomly, as well as all records if omitted.
+    key: Option<Pointer>,
+}
+
+impl SampleConfig {
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn key(&self) -> Option<&Pointer> {
+        self.key.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DedupConfig {
+    /// Fields, which values identify identical records, `["message"]` by default.
+    key: Option<Vec<Pointer>>,
+    /// Time window in seconds to collapse identical records within, 10 by default.
+    window: Option<u64>,
+    /// Maximum number of tracked keys, 10000 by default.
+    ///
+    /// Records with new keys are passed through when the limit is reached.
+    max_keys: Option<usize>,
+}
+
+impl DedupConfig {
+    pub fn key(&self) -> Vec<Pointer> {
+        self.key.clone().unwrap_or_else(|| vec!["message".parse().unwrap()])
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window.unwrap_or(10))
+    }
+
+    pub fn max_keys(&self) -> usize {
+        self.max_keys.unwrap_or(10000)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MultilineConfig {
+    /// Field with the line, "message" by default.
+    field: Option<Pointer>,
+    /// Fields, which values identify a stream, i.e. the source peer or file path. Lines of
+    /// different streams are never merged.
+    ///
+    /// All records form a single stream if omitted, so lines of interleaved producers may be
+    /// merged together. Use the `peer` option of the UDP source to tell its senders apart.
+    #[serde(default)]
+    key: Vec<Pointer>,
+    /// Regular expression, matching the first line of a record. All other lines are
+    /// continuations.
+    start: Option<String>,
+    /// Regular expression, matching continuation lines.
+    #[serde(rename="continue")]
+    continuation: Option<String>,
+    /// Whether lines starting with whitespace are continuations. True by default if neither
+    /// `start` nor `continue` is specified.
+    indent: Option<bool>,
+    /// Maximum number of lines in a merged record, 500 by default.
+    max_lines: Option<usize>,
+    /// Maximum size of a merged record field in bytes, 64 KiB by default.
+    max_bytes: Option<usize>,
+    /// Time in seconds to wait for continuation lines, 1 by default.
+    timeout: Option<u64>,
+    /// Maximum number of streams being merged at once, 10000 by default.
+    ///
+    /// Records of new streams are passed through unmerged when the limit is reached.
+    max_keys: Option<usize>,
+}
+
+impl MultilineConfig {
+    pub fn field(&self) -> Pointer {
+        self.field.clone().unwrap_or_else(|| "message".parse().unwrap())
+    }
+
+    pub fn key(&self) -> &Vec<Pointer> {
+        &self.key
+    }
+
+    pub fn start(&self) -> Option<&str> {
+        self.start.as_ref().map(|val| val.as_str())
+    }
+
+    pub fn continuation(&self) -> Option<&str> {
+        self.continuation.as_ref().map(|val| val.as_str())
+    }
+
+    pub fn indent(&self) -> bool {
+        self.indent.unwrap_or(self.start.is_none() && self.continuation.is_none())
+    }
+
+    pub fn max_lines(&self) -> usize {
+        self.max_lines.unwrap_or(500)
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes.unwrap_or(64 * 1024)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(1))
+    }
+
+    pub fn max_keys(&self) -> usize {
+        self.max_keys.unwrap_or(10000)
+    }
+}
+
+/// Describes what to do with sensitive data found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Replace the match with the mask string.
+    Mask,
+    /// Replace the match with its keyed hash, so redacted values can still be joined.
+    Hash,
+    /// Remove the whole field. Requires explicit `fields`, so essential fields like `message`
+    /// are never removed by accident.
+    Drop,
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(val: &str) -> Result<Action, String> {
+        match val {
+            "mask" => Ok(Action::Mask),
+            "hash" => Ok(Action::Hash),
+            "drop" => Ok(Action::Drop),
+            _ => Err(format!("invalid action '{}', must be one of 'mask', 'hash' or 'drop'", val)),
+        }
+    }
+}
+
+impl Deserialize for Action {
+    fn deserialize<D>(de: &mut D) -> Result<Action, D::Error>
+        where D: Deserializer
+    {
+        let val = try!(String::deserialize(de));
+        val.parse().map_err(de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactConfig {
+    /// Fields to inspect. All string fields are inspected recursively if omitted.
+    fields: Option<Vec<Pointer>>,
+    /// Built-in detectors to use: "email", "ipv4", "ipv6", "credit_card" and "bearer". All of
+    /// them are used if omitted.
+    detectors: Option<Vec<String>>,
+    /// Custom detectors, mapping names to regular expressions.
+    ///
+    /// If the expression has a group named `secret`, only that group is redacted.
+    #[serde(default)]
+    patterns: BTreeMap<String, String>,
+    /// Action, "mask" by default.
+    action: Option<Action>,
+    /// Replacement for masked data, "[REDACTED]" by default.
+    mask: Option<String>,
+    /// Secret key for hashing, required for "hash" action.
+    ///
+    /// Consider using a file reference to keep it out of the config, i.e. "@file:/etc/key".
+    key: Option<String>,
+}
+
+impl RedactConfig {
+    pub fn fields(&self) -> Option<&Vec<Pointer>> {
+        self.fields.as_ref()
+    }
+
+    pub fn detectors(&self) -> Option<&Vec<String>> {
+        self.detectors.as_ref()
+    }
+
+    pub fn patterns(&self) -> &BTreeMap<String, String> {
+        &self.patterns
+    }
+
+    pub fn action(&self) -> Action {
+        self.action.unwrap_or(Action::Mask)
+    }
+
+    pub fn mask(&self) -> &str {
+        self.mask.as_ref().map(|mask| mask.as_str()).unwrap_or("[REDACTED]")
+    }
+
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_ref().map(|key| key.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptConfig {
+    /// Lua source code, that defines the processing function.
+    ///
+    /// Consider using a file reference to keep it out of the config, i.e. "@file:/etc/filter.lua".
+    /// Inline code is interpolated, so a literal `${` or `$$` must be written as `$${` or `$$$$`.
+    script: String,
+    /// Name of the function to call for each record, "process" by default.
+    function: Option<String>,
+    /// Maximum execution time of the function in milliseconds, 10 by default.
+    timeout: Option<u64>,
+    /// Maximum memory used by the interpreter in bytes, 16 MiB by default.
+    ///
+    /// Allocations beyond the limit fail, which aborts the running call with an error.
+    memory: Option<usize>,
+    /// What to do with records, that the function failed to process.
+    on_error: Option<OnMismatch>,
+}
+
+impl ScriptConfig {
+    pub fn script(&self) -> &str {
+        &self.script
+    }
+
+    pub fn function(&self) -> &str {
+        self.function.as_ref().map(|val| val.as_str()).unwrap_or("process")
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout.unwrap_or(10))
+    }
+
+    pub fn memory(&self) -> usize {
+        self.memory.unwrap_or(16 * 1024 * 1024)
+    }
+
+    pub fn on_error(&self) -> OnMismatch {
+        self.on_error.unwrap_or(OnMismatch::Keep)
+    }
+}
+
+/// Lookup table file format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TableFormat {
+    /// Comma-separated values with a header row.
+    Csv,
+    /// Either an object mapping keys to rows or an array of rows.
+    Json,
+}
+
+impl FromStr for TableFormat {
+    type Err = String;
+
+    fn from_str(val: &str) -> Result<TableFormat, String> {
+        match val {
+            "csv" => Ok(TableFormat::Csv),
+            "json" => Ok(TableFormat::Json),
+            _ => Err(format!("invalid table format '{}', must be one of 'csv' or 'json'", val)),
+        }
+    }
+}
+
+impl Deserialize for TableFormat {
+    fn deserialize<D>(de: &mut D) -> Result<TableFormat, D::Error>
+        where D: Deserializer
+    {
+        let val = try!(String::deserialize(de));
+        val.parse().map_err(de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LookupConfig {
+    /// Path to the table file.
+    ///
+    /// Relative paths are resolved relative to the directory of the main config file.
+    path: String,
+    /// Table format, detected by the file extension by default.
+    format: Option<TableFormat>,
+    /// Field to join on.
+    field: Pointer,
+    /// Table column to join on, the first CSV column by default. Required for JSON arrays and
+    /// ignored for JSON objects, which keys are used instead.
+    key: Option<String>,
+    /// Columns to merge, all except the key one by default.
+    columns: Option<Vec<String>>,
+    /// Field to store matched columns in. Columns are merged into the record root if omitted.
+    target: Option<Pointer>,
+    on_mismatch: Option<OnMismatch>,
+    /// Whether to overwrite existing fields, true by default.
+    overwrite: Option<bool>,
+}
+
+impl LookupConfig {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn format(&self) -> TableFormat {
+        match self.format {
+            Some(format) => format,
+            None if self.path.ends_with(".json") => TableFormat::Json,
+            None => TableFormat::Csv,
+        }
+    }
+
+    pub fn field(&self) -> &Pointer {
+        &self.field
+    }
+
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_ref().map(|val| val.as_str())
+    }
+
+    pub fn columns(&self) -> Option<&Vec<String>> {
+        self.columns.as_ref()
+    }
+
+    pub fn target(&self) -> Option<&Pointer> {
+        self.target.as_ref()
+    }
+
+    pub fn on_mismatch(&self) -> OnMismatch {
+        self.on_mismatch.unwrap_or(OnMismatch::Keep)
+    }
+
+    pub fn overwrite(&self) -> bool {
+        self.overwrite.unwrap_or(true)
+    }
+}
+
+/// Expected field type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldType {
+    String,
+    /// Signed or unsigned integer.
+    Integer,
+    /// Either integer or floating point number.
+    Number,
+    Boolean,
+    Object,
+    Array,
+}
+
+impl FieldType {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            FieldType::String => "string",
+            FieldType::Integer => "integer",
+            FieldType::Number => "number",
+            FieldType::Boolean => "boolean",
+            FieldType::Object => "object",
+            FieldType::Array => "array",
+        }
+    }
+}
+
+impl FromStr for FieldType {
+    type Err = String;
+
+    fn from_str(val: &str) -> Result<FieldType, String> {
+        match val {
+            "string" => Ok(FieldType::String),
+            "integer" => Ok(FieldType::Integer),
+            "number" => Ok(FieldType::Number),
+            "boolean" => Ok(FieldType::Boolean),
+            "object" => Ok(FieldType::Object),
+            "array" => Ok(FieldType::Array),
+            _ => {
+                Err(format!("invalid field type '{}', must be one of 'string', 'integer', \
+                    'number', 'boolean', 'object' or 'array'", val))
+            }
+        }
+    }
+}
+
+impl Deserialize for FieldType {
+    fn deserialize<D>(de: &mut D) -> Result<FieldType, D::Error>
+        where D: Deserializer
+    {
+        let val = try!(String::deserialize(de));
+        val.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Describes what to do with records, that fail validation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnInvalid {
+    /// Pass the record, appending a failure tag and the list of errors.
+    Annotate,
+    /// Drop the record.
+    Drop,
+    /// Replace the record with a dead letter, that wraps the original record together with the
+    /// list of errors, so it can be routed to a separate output.
+    DeadLetter,
+}
+
+impl FromStr for OnInvalid {
+    type Err = String;
+
+    fn from_str(val: &str) -> Result<OnInvalid, String> {
+        match val {
+            "annotate" => Ok(OnInvalid::Annotate),
+            "drop" => Ok(OnInvalid::Drop),
+            "deadletter" => Ok(OnInvalid::DeadLetter),
+            _ => {
+                Err(format!("invalid policy '{}', must be one of 'annotate', 'drop' or \
+                    'deadletter'", val))
+            }
+        }
+    }
+}
+
+impl Deserialize for OnInvalid {
+    fn deserialize<D>(de: &mut D) -> Result<OnInvalid, D::Error>
+        where D: Deserializer
+    {
+        let val = try!(String::deserialize(de));
+        val.parse().map_err(de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldSchema {
+    field: Pointer,
+    /// Expected type. Any type is allowed if omitted.
+    #[serde(rename="type")]
+    ty: Option<FieldType>,
+    /// Whether the field must be present and not null, false by default.
+    required: Option<bool>,
+    /// Value to insert if the field is missing or null.
+    default: Option<Value>,
+}
+
+impl FieldSchema {
+    pub fn field(&self) -> &Pointer {
+        &self.field
+    }
+
+    pub fn ty(&self) -> Option<FieldType> {
+        self.ty
+    }
+
+    pub fn required(&self) -> bool {
+        self.required.unwrap_or(false)
+    }
+
+    pub fn default(&self) -> Option<&Value> {
+        self.default.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchemaConfig {
+    fields: Vec<FieldSchema>,
+    /// Whether to convert values of mismatched types where it is lossless, i.e. "42" into 42 for
+    /// integer fields, true by default.
+    coerce: Option<bool>,
+    on_invalid: Option<OnInvalid>,
+}
+
+impl SchemaConfig {
+    pub fn fields(&self) -> &Vec<FieldSchema> {
+        &self.fields
+    }
+
+    pub fn coerce(&self) -> bool {
+        self.coerce.unwrap_or(true)
+    }
+
+    pub fn on_invalid(&self) -> OnInvalid {
+        self.on_invalid.unwrap_or(OnInvalid::Annotate)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AggregateConfig {
+    /// Fields to group records by. All records form a single group if omitted.
+    #[serde(default)]
+    key: Vec<Pointer>,
+    /// Numeric field to compute statistics of, i.e. "duration". Only record counts are computed
+    /// if omitted.
+    field: Option<Pointer>,
+    /// Window length in seconds, 60 by default.
+    window: Option<u64>,
+    /// Percentiles of the field to compute, `[50, 90, 99]` by default.
+    percentiles: Option<Vec<f64>>,
+    /// Maximum number of field values kept per group for percentile estimation, 10000 by
+    /// default. Values are sampled uniformly when the limit is reached.
+    max_samples: Option<usize>,
+    /// Maximum number of groups per window, 10000 by default.
+    ///
+    /// Records of new groups are not aggregated when the limit is reached.
+    max_keys: Option<usize>,
+    /// Whether to pass aggregated records through, false by default.
+    pass: Option<bool>,
+}
+
+impl AggregateConfig {
+    pub fn key(&self) -> &Vec<Pointer> {
+        &self.key
+    }
+
+    pub fn field(&self) -> Option<&Pointer> {
+        self.field.as_ref()
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window.unwrap_or(60))
+    }
+
+    pub fn percentiles(&self) -> Vec<f64> {
+        self.percentiles.clone().unwrap_or_else(|| vec![50.0, 90.0, 99.0])
+    }
+
+    pub fn max_samples(&self) -> usize {
+        self.max_samples.unwrap_or(10000)
+    }
+
+    pub fn max_keys(&self) -> usize {
+        self.max_keys.unwrap_or(10000)
+    }
+
+    pub fn pass(&self) -> bool {
+        self.pass.unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TraceConfig {
+    /// Field with the trace id, "trace_id" by default.
+    trace_id: Option<Pointer>,
+    /// Field with the span id, "span_id" by default.
+    span_id: Option<Pointer>,
+    /// Field with the parent span id, "parent_id" by default.
+    parent_id: Option<Pointer>,
+    /// Time window in seconds to buffer records of a trace since its first record, 10 by default.
+    window: Option<u64>,
+    /// Maximum number of buffered traces, 10000 by default.
+    ///
+    /// Records of new traces are passed through unchanged when the limit is reached.
+    max_traces: Option<usize>,
+    /// Maximum number of records per trace, 1000 by default. The trace is emitted early when the
+    /// limit is reached.
+    max_records: Option<usize>,
+}
+
+impl TraceConfig {
+    pub fn trace_id(&self) -> Pointer {
+        self.trace_id.clone().unwrap_or_else(|| "trace_id".parse().unwrap())
+    }
+
+    pub fn span_id(&self) -> Pointer {
+        self.span_id.clone().unwrap_or_else(|| "span_id".parse().unwrap())
+    }
+
+    pub fn parent_id(&self) -> Pointer {
+        self.parent_id.clone().unwrap_or_else(|| "parent_id".parse().unwrap())
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window.unwrap_or(10))
+    }
+
+    pub fn max_traces(&self) -> usize {
+        self.max_traces.unwrap_or(10000)
+    }
+
+    pub fn max_records(&self) -> usize {
+        self.max_records.unwrap_or(1000)
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde_json::{self, Value};
+
+use {Config, Record};
+use config;
+use filter::{self, Filter, FilterFactory};
+use filter::config::{DecodeConfig, Format, OnMismatch};
+use filter::pointer::Pointer;
+use metrics::{Counter, Scope};
+
+/// Tag appended to records, that failed to decode, with `tag` mismatch policy.
+const MISMATCH_TAG: &'static str = "_decodefailure";
+
+/// Decodes a JSON object from the given string.
+///
+/// The object may be embedded into the surrounding text, i.e. `request finished {"status": 200}`.
+fn json(val: &str) -> Option<BTreeMap<String, Value>> {
+    let start = match val.find('{') {
+        Some(start) => start,
+        None => return None,
+    };
+
+    let end = match val.rfind('}') {
+        Some(end) if end > start => end,
+        _ => return None,
+    };
+
+    match serde_json::from_str(&val[start..end + 1]) {
+        Ok(Value::Object(map)) => Some(map),
+        _ => None,
+    }
+}
+
+/// Decodes logfmt-encoded pairs, i.e. `method=GET path="/api v2" cached`.
+///
+/// Keys without values are decoded as `true`, unless `strict` is set, in which case they make
+/// the whole value invalid. Returns nothing unless there is at least one `key=value` pair.
+///
+/// Strict mode is used for format detection, so plain text like `failed to send user=42` is not
+/// mistaken for logfmt.
+fn logfmt(val: &str, strict: bool) -> Option<BTreeMap<String, Value>> {
+    let mut map = BTreeMap::new();
+    let mut pairs = 0;
+    let mut chars = val.chars().peekable();
+
+    loop {
+        while chars.peek().map_or(false, |ch| ch.is_whitespace()) {
+            chars.next();
+        }
+
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(&ch) = chars.peek() {
+            if ch == '=' || ch.is_whitespace() {
+                break;
+            }
+
+            key.push(ch);
+            chars.next();
+        }
+
+        if chars.peek() != Some(&'=') {
+            if strict {
+                return None;
+            }
+
+            if !key.is_empty() {
+                map.insert(key, Value::Bool(true));
+            }
+            continue;
+        }
+
+        chars.next();
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => {
+                        match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some(ch) => value.push(ch),
+                            None => return None,
+                        }
+                    }
+                    Some(ch) => value.push(ch),
+                    // Unterminated quoted value.
+                    None => return None,
+                }
+            }
+        } else {
+            while let Some(&ch) = chars.peek() {
+                if ch.is_whitespace() {
+                    break;
+                }
+
+                value.push(ch);
+                chars.next();
+            }
+        }
+
+        if key.is_empty() {
+            return None;
+        }
+
+        map.insert(key, Value::String(value));
+        pairs += 1;
+    }
+
+    if pairs > 0 {
+        Some(map)
+    } else {
+        None
+    }
+}
+
+/// Decodes JSON or logfmt document embedded into a string field and merges it into the record.
+pub struct DecodeFilter {
+    field: Pointer,
+    format: Format,
+    target: Option<Pointer>,
+    remove: bool,
+    on_mismatch: OnMismatch,
+    overwrite: bool,
+    mismatches: Counter,
+}
+
+impl DecodeFilter {
+    fn new(cfg: DecodeConfig, metrics: &Scope) -> DecodeFilter {
+        DecodeFilter {
+            field: cfg.field(),
+            format: cfg.format(),
+            target: cfg.target().cloned(),
+            remove: cfg.remove(),
+            on_mismatch: cfg.on_mismatch(),
+            overwrite: cfg.overwrite(),
+            mismatches: metrics.counter("zenlog_filter_mismatches_total",
+                "Number of records the filter failed to parse"),
+        }
+    }
+
+    fn decode(&self, val: &str) -> Option<BTreeMap<String, Value>> {
+        match self.format {
+            Format::Auto => {
+                if val.contains('{') {
+                    json(val).or_else(|| logfmt(val, true))
+                } else {
+                    logfmt(val, true)
+                }
+            }
+            Format::Json => json(val),
+            Format::Logfmt => logfmt(val, false),
+        }
+    }
+
+    fn merge(&self, record: &mut Record, map: BTreeMap<String, Value>) {
+        // The source field is removed first, so that decoded data may take its place, but is
+        // restored if nothing has been merged.
+        let source = if self.remove {
+            self.field.remove(record)
+        } else {
+            None
+        };
+
+        let merged = match self.target {
+            Some(ref target) => {
+                if self.overwrite || target.find(record).is_none() {
+                    target.insert(record, Value::Object(map))
+                } else {
+                    false
+                }
+            }
+            None => {
+                let mut merged = false;
+
+                if let Some(record) = record.as_object_mut() {
+                    for (key, val) in map {
+                        // The source field is never clobbered, unless it is removed anyway.
+                        if !self.remove && self.field.overlaps(&Pointer::field(&key)) {
+                            continue;
+                        }
+
+                        if self.overwrite || !record.contains_key(&key) {
+                            record.insert(key, val);
+                            merged = true;
+                        }
+                    }
+                }
+
+                merged
+            }
+        };
+
+        if let (false, Some(source)) = (merged, source) {
+            self.field.insert(record, source);
+        }
+    }
+}
+
+impl Filter for DecodeFilter {
+    fn filter(&mut self, mut record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        let decoded = match self.field.find(&record).and_then(|val| val.as_string()) {
+            Some(val) => self.decode(val),
+            None => None,
+        };
+
+        match decoded {
+            Some(map) => self.merge(Arc::make_mut(&mut record), map),
+            None => {
+                self.mismatches.inc();
+
+                match self.on_mismatch {
+                    OnMismatch::Keep => {}
+                    OnMismatch::Drop => return,
+                    OnMismatch::Tag => filter::tag(&mut record, MISMATCH_TAG),
+                }
+            }
+        }
+
+        out.push(record);
+    }
+}
+
+impl FilterFactory for DecodeFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "decode"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: DecodeConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(DecodeFilter::new(cfg, metrics)))
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::{self, Value};
+
+use {Config, Record};
+use config;
+use filter::{self, Filter, FilterFactory};
+use filter::config::DedupConfig;
+use filter::pointer::Pointer;
+use metrics::{Counter, Scope};
+
+/// Group of identical records within a single window.
+struct Group {
+    started: Instant,
+    /// The last repeated record, which is emitted as a summary.
+    last: Option<Arc<Record>>,
+    repeated: u64,
+    first_timestamp: Value,
+    last_timestamp: Value,
+}
+
+impl Group {
+    /// Returns the summary record, if there were repeats.
+    fn summarize(self) -> Option<Arc<Record>> {
+        let (repeated, first, last) = (self.repeated, self.first_timestamp, self.last_timestamp);
+
+        self.last.map(|mut record| {
+            if let Some(map) = Arc::make_mut(&mut record).as_object_mut() {
+                map.insert("repeated".to_owned(), Value::U64(repeated));
+                map.insert("first_timestamp".to_owned(), first);
+                map.insert("last_timestamp".to_owned(), last);
+            }
+
+            record
+        })
+    }
+}
+
+fn timestamp(record: &Record) -> Value {
+    record.find("timestamp")
+        .cloned()
+        .unwrap_or_else(|| Value::I64(filter::timestamp()))
+}
+
+/// Collapses identical records within a time window.
+///
+/// The first record of a group is passed immediately. Its repeats are suppressed until the
+/// window expires, when the last of them is emitted with the `repeated` counter and both
+/// `first_timestamp` and `last_timestamp` fields attached. Pending groups are also emitted on
+/// pipeline shutdown.
+pub struct DedupFilter {
+    key: Vec<Pointer>,
+    window: Duration,
+    max_keys: usize,
+    groups: HashMap<String, Group>,
+    suppressed: Counter,
+}
+
+impl DedupFilter {
+    fn new(cfg: DedupConfig, metrics: &Scope) -> DedupFilter {
+        DedupFilter {
+            key: cfg.key(),
+            window: cfg.window(),
+            max_keys: cfg.max_keys(),
+            groups: HashMap::new(),
+            suppressed: metrics.counter("zenlog_filter_suppressed_total",
+                "Number of records suppressed by the filter"),
+        }
+    }
+}
+
+impl Filter for DedupFilter {
+    fn filter(&mut self, record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        let id = {
+            let key: Vec<Option<&Value>> = self.key.iter().map(|path| path.find(&record)).collect();
+            serde_json::to_string(&key).unwrap_or_else(|_| String::new())
+        };
+
+        let now = Instant::now();
+
+        let expired = match self.groups.get_mut(&id) {
+            Some(group) => {
+                if now.duration_since(group.started) < self.window {
+                    group.repeated += 1;
+                    group.last_timestamp = timestamp(&record);
+                    group.last = Some(record);
+                    self.suppressed.inc();
+                    return;
+                }
+
+                true
+            }
+            None => false,
+        };
+
+        if expired {
+            if let Some(summary) = self.groups.remove(&id).and_then(Group::summarize) {
+                out.push(summary);
+            }
+        } else if self.groups.len() >= self.max_keys {
+            out.push(record);
+            return;
+        }
+
+        let group = Group {
+            started: now,
+            last: None,
+            repeated: 0,
+            first_timestamp: timestamp(&record),
+            last_timestamp: Value::Null,
+        };
+
+        self.groups.insert(id, group);
+        out.push(record);
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Arc<Record>>) {
+        let window = self.window;
+        let expired: Vec<String> = self.groups.iter()
+            .filter(|&(_, group)| now.duration_since(group.started) >= window)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in expired {
+            if let Some(summary) = self.groups.remove(&id).and_then(Group::summarize) {
+                out.push(summary);
+            }
+        }
+    }
+
+    fn flush(&mut self, out: &mut Vec<Arc<Record>>) {
+        for (_, group) in self.groups.drain() {
+            if let Some(summary) = group.summarize() {
+                out.push(summary);
+            }
+        }
+    }
+}
+
+impl FilterFactory for DedupFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "dedup"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: DedupConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(DedupFilter::new(cfg, metrics)))
+    }
+}
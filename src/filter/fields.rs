@@ -0,0 +1,127 @@
+use std::env;
+use std::error::Error;
+use std::ffi::CStr;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use libc;
+use serde_json::Value;
+
+use {Config, Record};
+use config;
+use filter::{self, Filter, FilterFactory};
+use filter::config::{FieldsConfig, HostnameConfig};
+use metrics::Scope;
+
+/// Adds the local hostname to every record.
+///
+/// The hostname is resolved once, when the filter is constructed.
+pub struct HostnameFilter {
+    field: String,
+    hostname: String,
+    overwrite: bool,
+}
+
+fn hostname() -> Result<String, io::Error> {
+    let mut buf = [0u8; 256];
+
+    let rc = unsafe {
+        libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len() as libc::size_t)
+    };
+
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // The result may be not null-terminated if truncated.
+    buf[buf.len() - 1] = 0;
+
+    let hostname = unsafe { CStr::from_ptr(buf.as_ptr() as *const libc::c_char) };
+
+    Ok(hostname.to_string_lossy().into_owned())
+}
+
+impl Filter for HostnameFilter {
+    fn filter(&mut self, mut record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        filter::set(&mut record, &self.field, Value::String(self.hostname.clone()),
+            self.overwrite);
+        out.push(record);
+    }
+}
+
+impl FilterFactory for HostnameFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "hostname"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, _metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: HostnameConfig = try!(config::decode(cfg));
+
+        let filter = HostnameFilter {
+            field: cfg.field().to_owned(),
+            hostname: try!(hostname().map_err(|err| format!("failed to get hostname: {}", err))),
+            overwrite: cfg.overwrite(),
+        };
+
+        Ok(Box::new(filter))
+    }
+}
+
+/// Adds static fields to every record.
+///
+/// Field values are either specified explicitly, taken from environment variables or set to the
+/// pipeline name.
+pub struct FieldsFilter {
+    fields: Vec<(String, Value)>,
+    overwrite: bool,
+}
+
+impl Filter for FieldsFilter {
+    fn filter(&mut self, mut record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        for &(ref key, ref value) in &self.fields {
+            filter::set(&mut record, key, value.clone(), self.overwrite);
+        }
+
+        out.push(record);
+    }
+}
+
+impl FilterFactory for FieldsFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "fields"
+    }
+
+    fn from(cfg: &Config, pipeline: &str, _base: &Path, _metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: FieldsConfig = try!(config::decode(cfg));
+
+        let mut fields: Vec<(String, Value)> = cfg.fields().iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        for (key, var) in cfg.env() {
+            let value = try!(env::var(var)
+                .map_err(|err| format!("failed to read environment variable '{}': {}", var, err)));
+            fields.push((key.clone(), Value::String(value)));
+        }
+
+        if let Some(key) = cfg.pipeline() {
+            fields.push((key.to_owned(), Value::String(pipeline.to_owned())));
+        }
+
+        let filter = FieldsFilter {
+            fields: fields,
+            overwrite: cfg.overwrite(),
+        };
+
+        Ok(Box::new(filter))
+    }
+}
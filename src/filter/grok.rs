@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use regex::Regex;
+use serde_json::Value;
+
+use {Config, Record};
+use config;
+use filter::{self, Filter, FilterFactory};
+use filter::config::{GrokConfig, OnMismatch};
+use filter::pointer::Pointer;
+use metrics::{Counter, Scope};
+
+/// Tag appended to records, that match no pattern, with `tag` mismatch policy.
+const MISMATCH_TAG: &'static str = "_grokparsefailure";
+
+/// Maximum nesting level of pattern references, used mainly to detect cycles.
+const MAX_DEPTH: usize = 32;
+
+/// Prefix of generated capture group names.
+const GROUP_PREFIX: &'static str = "__grok";
+
+/// Built-in named patterns.
+const LIBRARY: &'static [(&'static str, &'static str)] = &[
+    ("USERNAME", r"[a-zA-Z0-9._-]+"),
+    ("USER", r"%{USERNAME}"),
+    ("INT", r"[+-]?[0-9]+"),
+    ("POSINT", r"\b[1-9][0-9]*\b"),
+    ("NONNEGINT", r"\b[0-9]+\b"),
+    ("BASE10NUM", r"[+-]?(?:[0-9]+(?:\.[0-9]+)?|\.[0-9]+)"),
+    ("NUMBER", r"%{BASE10NUM}"),
+    ("BASE16NUM", r"[+-]?(?:0x)?[0-9A-Fa-f]+"),
+    ("WORD", r"\b\w+\b"),
+    ("NOTSPACE", r"\S+"),
+    ("SPACE", r"\s*"),
+    ("DATA", r".*?"),
+    ("GREEDYDATA", r".*"),
+    ("QUOTEDSTRING", r#""(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*'"#),
+    ("QS", r"%{QUOTEDSTRING}"),
+    ("UUID", r"[A-Fa-f0-9]{8}-(?:[A-Fa-f0-9]{4}-){3}[A-Fa-f0-9]{12}"),
+    ("IPV4", concat!(r"(?:(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\.){3}",
+        r"(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])")),
+    ("IPV6", r"(?:[0-9A-Fa-f]{0,4}:){2,7}[0-9A-Fa-f]{0,4}"),
+    ("IP", r"(?:%{IPV6}|%{IPV4})"),
+    ("HOSTNAME", concat!(r"\b[0-9A-Za-z][0-9A-Za-z-]{0,62}",
+        r"(?:\.[0-9A-Za-z][0-9A-Za-z-]{0,62})*\.?\b")),
+    ("IPORHOST", r"(?:%{IP}|%{HOSTNAME})"),
+    ("HOSTPORT", r"%{IPORHOST}:%{POSINT}"),
+    ("PATH", r"(?:/[^\s?#]*)+"),
+    ("URIPATH", r"(?:/[A-Za-z0-9$.+!*'(),~:;=@#%&_\-]*)+"),
+    ("URIPARAM", r"\?[A-Za-z0-9$.+!*'|(),~@#%&/=:;_?\-\[\]<>]*"),
+    ("URIPATHPARAM", r"%{URIPATH}(?:%{URIPARAM})?"),
+    ("HTTPMETHOD", r"\b(?:GET|HEAD|POST|PUT|DELETE|CONNECT|OPTIONS|TRACE|PATCH)\b"),
+    ("LOGLEVEL", r"(?i:trace|debug|info|notice|warn(?:ing)?|err(?:or)?|crit(?:ical)?|fatal)"),
+    ("MONTH", concat!(r"\b(?:Jan(?:uary)?|Feb(?:ruary)?|Mar(?:ch)?|Apr(?:il)?|May|June?|July?",
+        r"|Aug(?:ust)?|Sep(?:tember)?|Oct(?:ober)?|Nov(?:ember)?|Dec(?:ember)?)\b")),
+    ("MONTHNUM", r"(?:0?[1-9]|1[0-2])"),
+    ("MONTHDAY", r"(?:0[1-9]|[12][0-9]|3[01]|[1-9])"),
+    ("YEAR", r"[0-9]{4}"),
+    ("HOUR", r"(?:2[0123]|[01]?[0-9])"),
+    ("MINUTE", r"(?:[0-5][0-9])"),
+    ("SECOND", r"(?:(?:[0-5]?[0-9]|60)(?:[:.,][0-9]+)?)"),
+    ("TIME", r"%{HOUR}:%{MINUTE}(?::%{SECOND})?"),
+    ("ISO8601_TIMEZONE", r"(?:Z|[+-]%{HOUR}(?::?%{MINUTE}))"),
+    ("TIMESTAMP_ISO8601", concat!(r"%{YEAR}-%{MONTHNUM}-%{MONTHDAY}[T ]%{HOUR}:?%{MINUTE}",
+        r"(?::?%{SECOND})?%{ISO8601_TIMEZONE}?")),
+    ("HTTPDATE", r"%{MONTHDAY}/%{MONTH}/%{YEAR}:%{TIME} [+-]?[0-9]{4}"),
+    ("SYSLOGTIMESTAMP", r"%{MONTH} +%{MONTHDAY} %{TIME}"),
+    ("TIMESTAMP", r"(?:%{TIMESTAMP_ISO8601}|%{HTTPDATE}|%{SYSLOGTIMESTAMP})"),
+];
+
+/// Type, the captured string is converted into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Conversion {
+    Str,
+    Int,
+    Float,
+    Bool,
+}
+
+impl Conversion {
+    /// Converts the captured string, leaving it as is if the conversion fails.
+    fn apply(&self, val: &str) -> Value {
+        let converted = match *self {
+            Conversion::Str => None,
+            Conversion::Int => val.parse().ok().map(Value::I64),
+            Conversion::Float => val.parse().ok().map(Value::F64),
+            Conversion::Bool => val.parse().ok().map(Value::Bool),
+        };
+
+        converted.unwrap_or_else(|| Value::String(val.to_owned()))
+    }
+}
+
+impl FromStr for Conversion {
+    type Err = String;
+
+    fn from_str(val: &str) -> Result<Conversion, String> {
+        match val {
+            "string" => Ok(Conversion::Str),
+            "int" => Ok(Conversion::Int),
+            "float" => Ok(Conversion::Float),
+            "bool" => Ok(Conversion::Bool),
+            _ => Err(format!("invalid type '{}', must be one of 'string', 'int', 'float' or 'bool'",
+                val)),
+        }
+    }
+}
+
+struct Capture {
+    group: String,
+    field: Pointer,
+    conversion: Conversion,
+}
+
+/// Compiled pattern.
+struct Pattern {
+    regex: Regex,
+    captures: Vec<Capture>,
+}
+
+impl Pattern {
+    fn captures<'a>(&'a self, text: &str) -> Option<Vec<(&'a Pointer, Value)>> {
+        let caps = match self.regex.captures(text) {
+            Some(caps) => caps,
+            None => return None,
+        };
+
+        let fields = self.captures.iter()
+            .filter_map(|capture| {
+                caps.name(&capture.group)
+                    .map(|val| (&capture.field, capture.conversion.apply(val)))
+            })
+            .collect();
+
+        Some(fields)
+    }
+}
+
+/// Expands grok-style references into a plain regular expression.
+///
+/// A reference has the form `%{NAME}`, `%{NAME:field}` or `%{NAME:field:type}`, where `NAME`
+/// is either a built-in or user-defined pattern, `field` is a path to store the match in and
+/// `type` is one of `string`, `int`, `float` or `bool`.
+struct Compiler<'a> {
+    definitions: &'a BTreeMap<String, String>,
+    captures: Vec<Capture>,
+}
+
+impl<'a> Compiler<'a> {
+    fn compile(definitions: &BTreeMap<String, String>, pattern: &str) ->
+        Result<Pattern, Box<Error>>
+    {
+        let mut compiler = Compiler {
+            definitions: definitions,
+            captures: Vec::new(),
+        };
+
+        let expanded = try!(compiler.expand(pattern, 0));
+        let regex = try!(Regex::new(&expanded)
+            .map_err(|err| format!("invalid pattern '{}': {}", pattern, err)));
+
+        // Plain named groups are captured as string fields.
+        let mut captures = compiler.captures;
+        for name in regex.capture_names().filter_map(|name| name) {
+            if !name.starts_with(GROUP_PREFIX) {
+                captures.push(Capture {
+                    group: name.to_owned(),
+                    field: try!(name.parse()),
+                    conversion: Conversion::Str,
+                });
+            }
+        }
+
+        let pattern = Pattern {
+            regex: regex,
+            captures: captures,
+        };
+
+        Ok(pattern)
+    }
+
+    fn definition(&self, name: &str) -> Option<&'a str> {
+        self.definitions.get(name)
+            .map(|pattern| pattern.as_str())
+            .or_else(|| {
+                LIBRARY.iter()
+                    .find(|&&(key, _)| key == name)
+                    .map(|&(_, pattern)| pattern)
+            })
+    }
+
+    fn expand(&mut self, pattern: &str, depth: usize) -> Result<String, String> {
+        if depth > MAX_DEPTH {
+            return Err(format!("pattern nesting is too deep, probably recursive: '{}'", pattern));
+        }
+
+        let mut result = String::with_capacity(pattern.len());
+        let mut rest = pattern;
+
+        while let Some(pos) = rest.find("%{") {
+            result.push_str(&rest[..pos]);
+            rest = &rest[pos + 2..];
+
+            let end = try!(rest.find('}')
+                .ok_or(format!("unterminated pattern reference in '{}'", pattern)));
+            let reference = &rest[..end];
+            rest = &rest[end + 1..];
+
+            let mut parts = reference.splitn(3, ':');
+            let name = parts.next().unwrap_or("");
+            let field = parts.next();
+            let conversion = match parts.next() {
+                Some(ty) => try!(ty.parse()),
+                None => Conversion::Str,
+            };
+
+            let definition = try!(self.definition(name)
+                .ok_or(format!("unknown pattern '{}'", name)));
+            let expanded = try!(self.expand(definition, depth + 1));
+
+            match field {
+                Some(field) => {
+                    let group = format!("{}{}", GROUP_PREFIX, self.captures.len());
+                    result.push_str(&format!("(?P<{}>{})", group, expanded));
+
+                    self.captures.push(Capture {
+                        group: group,
+                        field: try!(field.parse()),
+                        conversion: conversion,
+                    });
+                }
+                None => result.push_str(&format!("(?:{})", expanded)),
+            }
+        }
+
+        result.push_str(rest);
+
+        Ok(result)
+    }
+}
+
+/// Parses a string field using named-capture patterns and merges captures into the record.
+///
+/// Patterns are tried in order until the first match.
+pub struct GrokFilter {
+    field: Pointer,
+    patterns: Vec<Pattern>,
+    on_mismatch: OnMismatch,
+    overwrite: bool,
+    mismatches: Counter,
+}
+
+impl GrokFilter {
+    fn new(cfg: GrokConfig, metrics: &Scope) -> Result<GrokFilter, Box<Error>> {
+        let mut patterns = Vec::new();
+
+        for pattern in cfg.patterns() {
+            patterns.push(try!(Compiler::compile(cfg.definitions(), pattern)));
+        }
+
+        let filter = GrokFilter {
+            field: cfg.field(),
+            patterns: patterns,
+            on_mismatch: cfg.on_mismatch(),
+            overwrite: cfg.overwrite(),
+            mismatches: metrics.counter("zenlog_filter_mismatches_total",
+                "Number of records the filter failed to parse"),
+        };
+
+        Ok(filter)
+    }
+}
+
+impl Filter for GrokFilter {
+    fn filter(&mut self, mut record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        let fields = match self.field.find(&record).and_then(|val| val.as_string()) {
+            Some(text) => self.patterns.iter().filter_map(|pattern| pattern.captures(text)).next(),
+            None => None,
+        };
+
+        match fields {
+            Some(fields) => {
+                for (field, val) in fields {
+                    if self.overwrite || field.find(&record).is_none() {
+                        field.insert(Arc::make_mut(&mut record), val);
+                    }
+                }
+            }
+            None => {
+                self.mismatches.inc();
+
+                match self.on_mismatch {
+                    OnMismatch::Keep => {}
+                    OnMismatch::Drop => return,
+                    OnMismatch::Tag => filter::tag(&mut record, MISMATCH_TAG),
+                }
+            }
+        }
+
+        out.push(record);
+    }
+}
+
+impl FilterFactory for GrokFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "grok"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: GrokConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(try!(GrokFilter::new(cfg, metrics))))
+    }
+}
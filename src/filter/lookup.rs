@@ -0,0 +1,290 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Instant, SystemTime};
+
+use csv;
+use serde_json::{self, Value};
+
+use {Config, Record};
+use config;
+use filter::{self, Filter, FilterFactory};
+use filter::config::{LookupConfig, OnMismatch, TableFormat};
+use filter::pointer::Pointer;
+use metrics::{Counter, Scope};
+
+/// Tag appended to records without matching rows with `tag` mismatch policy.
+const MISMATCH_TAG: &'static str = "_lookupfailure";
+
+type Row = BTreeMap<String, Value>;
+
+/// Table file modification time and size, which identify its contents.
+///
+/// The size is compared too, because the modification time granularity may be as coarse as a
+/// second, missing subsequent writes within it.
+type Version = Option<(SystemTime, u64)>;
+
+/// Returns the string representation of the given join key, so numeric ids match CSV strings.
+fn key(value: &Value) -> Option<String> {
+    match *value {
+        Value::String(ref val) => Some(val.clone()),
+        Value::I64(val) => Some(val.to_string()),
+        Value::U64(val) => Some(val.to_string()),
+        Value::Bool(val) => Some(val.to_string()),
+        _ => None,
+    }
+}
+
+/// Lookup table file description.
+struct Table {
+    path: PathBuf,
+    format: TableFormat,
+    key: Option<String>,
+    columns: Option<Vec<String>>,
+}
+
+impl Table {
+    fn version(&self) -> Version {
+        fs::metadata(&self.path).ok()
+            .and_then(|meta| meta.modified().ok().map(|time| (time, meta.len())))
+    }
+
+    /// Reads the table, returning rows by their keys.
+    fn load(&self) -> Result<HashMap<String, Row>, Box<Error>> {
+        let mut rows = match self.format {
+            TableFormat::Csv => try!(self.csv()),
+            TableFormat::Json => try!(self.json()),
+        };
+
+        if let Some(ref columns) = self.columns {
+            for row in rows.values_mut() {
+                let filtered = columns.iter()
+                    .filter_map(|column| row.remove(column).map(|val| (column.clone(), val)))
+                    .collect();
+                *row = filtered;
+            }
+        }
+
+        Ok(rows)
+    }
+
+    fn csv(&self) -> Result<HashMap<String, Row>, Box<Error>> {
+        let mut reader = try!(csv::Reader::from_file(&self.path));
+        let headers = try!(reader.headers());
+
+        let id = match self.key {
+            Some(ref key) => {
+                try!(headers.iter()
+                    .position(|header| header == key)
+                    .ok_or_else(|| format!("column '{}' not found", key)))
+            }
+            None => 0,
+        };
+
+        let mut rows = HashMap::new();
+
+        for record in reader.records() {
+            let record = try!(record);
+
+            let key = match record.get(id) {
+                Some(key) => key.clone(),
+                None => continue,
+            };
+
+            let row = headers.iter()
+                .zip(record.into_iter())
+                .enumerate()
+                .filter(|&(pos, _)| pos != id)
+                .map(|(_, (header, val))| (header.clone(), Value::String(val)))
+                .collect();
+
+            rows.insert(key, row);
+        }
+
+        Ok(rows)
+    }
+
+    fn json(&self) -> Result<HashMap<String, Row>, Box<Error>> {
+        let value: Value = try!(serde_json::from_reader(&try!(File::open(&self.path))));
+
+        let mut rows = HashMap::new();
+
+        match value {
+            Value::Object(map) => {
+                for (key, row) in map {
+                    match row {
+                        Value::Object(row) => {
+                            rows.insert(key, row);
+                        }
+                        _ => return Err(format!("row '{}' must be an object", key).into()),
+                    }
+                }
+            }
+            Value::Array(vec) => {
+                let column = try!(self.key.as_ref().ok_or("'key' is required for arrays"));
+
+                for row in vec {
+                    let mut row = match row {
+                        Value::Object(row) => row,
+                        _ => return Err("rows must be objects".into()),
+                    };
+
+                    if let Some(id) = row.remove(column).as_ref().and_then(key) {
+                        rows.insert(id, row);
+                    }
+                }
+            }
+            _ => return Err("table must be either an object or an array".into()),
+        }
+
+        Ok(rows)
+    }
+}
+
+/// Enriches records with columns of the lookup table row, that matches the given field.
+///
+/// The table is reloaded when the file modification time or size changes or on HUP signal. The
+/// previous table is kept if reloading fails.
+pub struct LookupFilter {
+    table: Table,
+    rows: HashMap<String, Row>,
+    version: Version,
+    field: Pointer,
+    target: Option<Pointer>,
+    on_mismatch: OnMismatch,
+    overwrite: bool,
+    tx: Sender<()>,
+    rx: Receiver<()>,
+    mismatches: Counter,
+    failures: Counter,
+}
+
+impl LookupFilter {
+    fn new(cfg: LookupConfig, base: &Path, metrics: &Scope) -> Result<LookupFilter, Box<Error>> {
+        let table = Table {
+            path: base.join(cfg.path()),
+            format: cfg.format(),
+            key: cfg.key().map(|key| key.to_owned()),
+            columns: cfg.columns().cloned(),
+        };
+
+        let version = table.version();
+        let rows = try!(table.load()
+            .map_err(|err| format!("failed to load table '{}': {}", table.path.display(), err)));
+
+        let (tx, rx) = mpsc::channel();
+
+        let filter = LookupFilter {
+            table: table,
+            rows: rows,
+            version: version,
+            field: cfg.field().clone(),
+            target: cfg.target().cloned(),
+            on_mismatch: cfg.on_mismatch(),
+            overwrite: cfg.overwrite(),
+            tx: tx,
+            rx: rx,
+            mismatches: metrics.counter("zenlog_filter_mismatches_total",
+                "Number of records without matching table rows"),
+            failures: metrics.counter("zenlog_filter_reload_failures_total",
+                "Number of failed lookup table reloads"),
+        };
+
+        Ok(filter)
+    }
+
+    fn reload(&mut self) {
+        // Remember the version before reading, so a broken file is not retried until it changes.
+        self.version = self.table.version();
+
+        match self.table.load() {
+            Ok(rows) => {
+                info!("reloaded lookup table '{}' with {} row(s)", self.table.path.display(),
+                    rows.len());
+                self.rows = rows;
+            }
+            Err(err) => {
+                self.failures.inc();
+                error!("failed to reload lookup table '{}': {}", self.table.path.display(),
+                    err);
+            }
+        }
+    }
+
+    fn merge(&self, record: &mut Record, row: &Row) {
+        match self.target {
+            Some(ref target) => {
+                if self.overwrite || target.find(record).is_none() {
+                    target.insert(record, Value::Object(row.clone()));
+                }
+            }
+            None => {
+                if let Some(record) = record.as_object_mut() {
+                    for (key, val) in row {
+                        if self.overwrite || !record.contains_key(key) {
+                            record.insert(key.clone(), val.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Filter for LookupFilter {
+    fn filter(&mut self, mut record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        let row = match self.field.find(&record).and_then(key) {
+            Some(id) => self.rows.get(&id),
+            None => None,
+        };
+
+        match row {
+            Some(row) => self.merge(Arc::make_mut(&mut record), row),
+            None => {
+                self.mismatches.inc();
+
+                match self.on_mismatch {
+                    OnMismatch::Keep => {}
+                    OnMismatch::Drop => return,
+                    OnMismatch::Tag => filter::tag(&mut record, MISMATCH_TAG),
+                }
+            }
+        }
+
+        out.push(record);
+    }
+
+    fn tick(&mut self, _now: Instant, _out: &mut Vec<Arc<Record>>) {
+        let mut hup = false;
+        while let Ok(()) = self.rx.try_recv() {
+            hup = true;
+        }
+
+        if hup || self.table.version() != self.version {
+            self.reload();
+        }
+    }
+
+    fn hup(&self) -> Option<Sender<()>> {
+        Some(self.tx.clone())
+    }
+}
+
+impl FilterFactory for LookupFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "lookup"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: LookupConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(try!(LookupFilter::new(cfg, base, metrics))))
+    }
+}
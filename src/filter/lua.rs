@@ -0,0 +1,517 @@
+//! Minimal wrapper around the embedded Lua 5.2 interpreter.
+//!
+//! Values are exchanged as JSON: objects and arrays become tables, JSON nulls become the global
+//! `null` value. Lua has no integer type, so integral numbers outside of the `f64` mantissa lose
+//! precision.
+//!
+//! Scripts run in a sandbox: libraries, that reach the outside world (`os`, `io`, `package`,
+//! `debug`), and functions, that load code from files or bytecode, are removed. The memory used
+//! by the interpreter is limited, so a single huge allocation, i.e. `string.rep("x", 1e10)`, fails
+//! with an error instead of exhausting the process.
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::ptr;
+use std::slice;
+use std::time::{Duration, Instant};
+
+use libc::{self, c_char, c_int, c_void, size_t};
+use lua52_sys as ffi;
+use serde_json::Value;
+
+/// Number of VM instructions between execution time checks.
+const HOOK_COUNT: c_int = 1000;
+
+/// Maximum nesting of exchanged values, which also protects from cyclic tables.
+const MAX_DEPTH: usize = 64;
+
+/// Globals removed from the environment of scripts.
+const UNSAFE_GLOBALS: &'static [&'static [u8]] = &[
+    b"os\0", b"io\0", b"package\0", b"debug\0", b"require\0", b"dofile\0", b"loadfile\0",
+    b"load\0",
+];
+
+thread_local! {
+    /// Time, when the currently running call must be aborted.
+    static DEADLINE: Cell<Option<Instant>> = Cell::new(None)
+}
+
+/// Memory accounting of a single interpreter.
+struct Memory {
+    used: Cell<usize>,
+    limit: Cell<usize>,
+}
+
+extern "C" fn alloc(ud: *mut c_void, ptr: *mut c_void, osize: size_t, nsize: size_t) ->
+    *mut c_void
+{
+    let memory = unsafe { &*(ud as *const Memory) };
+    let used = memory.used.get();
+
+    // For new blocks `osize` encodes the kind of the object rather than its size.
+    let osize = if ptr.is_null() { 0 } else { osize as usize };
+    let nsize = nsize as usize;
+
+    if nsize == 0 {
+        unsafe { libc::free(ptr) };
+        memory.used.set(used - osize);
+        return ptr::null_mut();
+    }
+
+    // Shrinking must never fail, so only growing is checked.
+    if nsize > osize && used - osize + nsize > memory.limit.get() {
+        return ptr::null_mut();
+    }
+
+    let block = unsafe { libc::realloc(ptr, nsize as size_t) };
+    if !block.is_null() {
+        memory.used.set(used - osize + nsize);
+    }
+
+    block
+}
+
+extern "C" fn hook(state: *mut ffi::lua_State, _ar: *mut ffi::lua_Debug) {
+    let expired = DEADLINE.with(|deadline| {
+        deadline.get().map_or(false, |deadline| Instant::now() >= deadline)
+    });
+
+    if expired {
+        unsafe {
+            let message = b"execution time limit exceeded\0";
+            ffi::lua_pushstring(state, message.as_ptr() as *const c_char);
+            ffi::lua_error(state);
+        }
+    }
+}
+
+/// Source code, fed to the loader in a single piece.
+struct Chunk<'a> {
+    data: &'a [u8],
+    done: bool,
+}
+
+extern "C" fn read(_state: *mut ffi::lua_State, ud: *mut c_void, size: *mut size_t) ->
+    *const c_char
+{
+    let chunk = unsafe { &mut *(ud as *mut Chunk) };
+
+    if chunk.done {
+        unsafe { *size = 0 };
+        return ptr::null();
+    }
+
+    chunk.done = true;
+    unsafe { *size = chunk.data.len() as size_t };
+
+    chunk.data.as_ptr() as *const c_char
+}
+
+/// Restores integers, that are represented as floats in Lua.
+fn number(val: f64) -> Value {
+    if val.fract() == 0.0 && val.abs() < 9007199254740992.0 {
+        if val < 0.0 {
+            Value::I64(val as i64)
+        } else {
+            Value::U64(val as u64)
+        }
+    } else {
+        Value::F64(val)
+    }
+}
+
+pub struct Lua {
+    state: *mut ffi::lua_State,
+    /// Referenced by the allocator, so must outlive the state.
+    memory: Box<Memory>,
+}
+
+// The state is owned exclusively and never shared between threads.
+unsafe impl Send for Lua {}
+
+impl Lua {
+    /// Creates the sandboxed interpreter, that may allocate at most `limit` bytes.
+    pub fn new(limit: usize) -> Lua {
+        // The limit is applied once the libraries are loaded, which must never fail.
+        let memory = Box::new(Memory {
+            used: Cell::new(0),
+            limit: Cell::new(usize::max_value()),
+        });
+        let state = unsafe {
+            ffi::lua_newstate(alloc, &*memory as *const Memory as *mut c_void)
+        };
+
+        if state.is_null() {
+            panic!("failed to allocate Lua state");
+        }
+
+        unsafe {
+            ffi::luaL_openlibs(state);
+
+            for name in UNSAFE_GLOBALS {
+                ffi::lua_pushnil(state);
+                ffi::lua_setglobal(state, name.as_ptr() as *const c_char);
+            }
+
+            ffi::lua_sethook(state, hook, ffi::LUA_MASKCOUNT, HOOK_COUNT);
+
+            ffi::lua_pushlightuserdata(state, ptr::null_mut());
+            ffi::lua_setglobal(state, b"null\0".as_ptr() as *const c_char);
+        }
+
+        memory.limit.set(limit);
+
+        Lua {
+            state: state,
+            memory: memory,
+        }
+    }
+
+    /// Compiles and runs the given source code, which usually defines functions.
+    pub fn load(&mut self, code: &str, timeout: Duration) -> Result<(), String> {
+        let mut chunk = Chunk {
+            data: code.as_bytes(),
+            done: false,
+        };
+
+        let rc = unsafe {
+            ffi::lua_load(self.state, read, &mut chunk as *mut Chunk as *mut c_void,
+                b"=script\0".as_ptr() as *const c_char, b"t\0".as_ptr() as *const c_char)
+        };
+
+        if rc != ffi::LUA_OK {
+            return Err(self.pop_error());
+        }
+
+        self.pcall(0, timeout).map(|_| ())
+    }
+
+    /// Returns whether there is a global function with the given name.
+    pub fn has_function(&mut self, name: &CStr) -> bool {
+        let limit = self.unlimit();
+
+        let result = unsafe {
+            ffi::lua_getglobal(self.state, name.as_ptr());
+            let result = ffi::lua_isfunction(self.state, -1);
+            ffi::lua_pop(self.state, 1);
+            result
+        };
+
+        self.memory.limit.set(limit);
+
+        result
+    }
+
+    /// Calls the global function with the given argument, returning all its results.
+    ///
+    /// The call is aborted with an error if it runs longer than the given timeout.
+    pub fn call(&mut self, name: &CStr, arg: &Value, timeout: Duration) ->
+        Result<Vec<Value>, String>
+    {
+        let base = unsafe { ffi::lua_gettop(self.state) };
+        let limit = self.unlimit();
+
+        unsafe { ffi::lua_getglobal(self.state, name.as_ptr()) };
+
+        let result = self.push(arg, 0).and_then(|()| {
+            if self.memory.used.get() > limit {
+                Err("not enough memory".to_owned())
+            } else {
+                Ok(())
+            }
+        });
+
+        if result.is_err() {
+            unsafe {
+                ffi::lua_settop(self.state, base);
+                // Release the partially built argument right away.
+                ffi::lua_gc(self.state, ffi::LUA_GCCOLLECT, 0);
+            }
+        }
+
+        self.memory.limit.set(limit);
+        try!(result);
+
+        self.pcall(1, timeout)
+    }
+
+    /// Lifts the memory limit, returning the previous one, which must be restored by the caller.
+    ///
+    /// Allocation failures outside of protected calls abort the whole process, so the limit is
+    /// lifted for such calls and checked afterwards instead.
+    fn unlimit(&mut self) -> usize {
+        let limit = self.memory.limit.get();
+        self.memory.limit.set(usize::max_value());
+        limit
+    }
+
+    /// Calls the function below its `nargs` arguments on the stack, returning all its results.
+    fn pcall(&mut self, nargs: c_int, timeout: Duration) -> Result<Vec<Value>, String> {
+        let base = unsafe { ffi::lua_gettop(self.state) } - nargs - 1;
+
+        DEADLINE.with(|deadline| deadline.set(Some(Instant::now() + timeout)));
+        let rc = unsafe { ffi::lua_pcall(self.state, nargs, ffi::MULTRET, 0) };
+        DEADLINE.with(|deadline| deadline.set(None));
+
+        if rc != ffi::LUA_OK {
+            return Err(self.pop_error());
+        }
+
+        let top = unsafe { ffi::lua_gettop(self.state) };
+        let result: Result<Vec<Value>, String> = (base + 1..top + 1)
+            .map(|idx| self.read(idx, 0))
+            .collect();
+
+        unsafe { ffi::lua_settop(self.state, base) };
+
+        result
+    }
+
+    fn pop_error(&mut self) -> String {
+        let message = unsafe {
+            if ffi::lua_type(self.state, -1) == ffi::LUA_TSTRING {
+                self.to_string(-1)
+            } else {
+                "unknown error".to_owned()
+            }
+        };
+
+        unsafe { ffi::lua_pop(self.state, 1) };
+
+        message
+    }
+
+    unsafe fn to_string(&self, idx: c_int) -> String {
+        let mut len = 0;
+        let ptr = ffi::lua_tolstring(self.state, idx, &mut len);
+        let bytes = slice::from_raw_parts(ptr as *const u8, len as usize);
+
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    unsafe fn typename(&self, ty: c_int) -> String {
+        CStr::from_ptr(ffi::lua_typename(self.state, ty)).to_string_lossy().into_owned()
+    }
+
+    unsafe fn push_str(&mut self, val: &str) {
+        ffi::lua_pushlstring(self.state, val.as_ptr() as *const c_char, val.len() as size_t);
+    }
+
+    /// Pushes the given value onto the stack.
+    ///
+    /// On error the stack may contain partially built values, so the caller must restore it.
+    fn push(&mut self, value: &Value, depth: usize) -> Result<(), String> {
+        if depth > MAX_DEPTH {
+            return Err("value is nested too deeply".to_owned());
+        }
+
+        unsafe {
+            if ffi::lua_checkstack(self.state, 3) == 0 {
+                return Err("stack overflow".to_owned());
+            }
+
+            match *value {
+                Value::Null => ffi::lua_pushlightuserdata(self.state, ptr::null_mut()),
+                Value::Bool(val) => ffi::lua_pushboolean(self.state, val as c_int),
+                Value::I64(val) => ffi::lua_pushnumber(self.state, val as f64),
+                Value::U64(val) => ffi::lua_pushnumber(self.state, val as f64),
+                Value::F64(val) => ffi::lua_pushnumber(self.state, val),
+                Value::String(ref val) => self.push_str(val),
+                Value::Array(ref vec) => {
+                    ffi::lua_createtable(self.state, vec.len() as c_int, 0);
+
+                    for (id, val) in vec.iter().enumerate() {
+                        try!(self.push(val, depth + 1));
+                        ffi::lua_rawseti(self.state, -2, id as c_int + 1);
+                    }
+                }
+                Value::Object(ref map) => {
+                    ffi::lua_createtable(self.state, 0, map.len() as c_int);
+
+                    for (key, val) in map {
+                        self.push_str(key);
+                        try!(self.push(val, depth + 1));
+                        ffi::lua_rawset(self.state, -3);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the value at the given stack index.
+    ///
+    /// On error the stack may contain iteration leftovers, so the caller must restore it.
+    fn read(&self, idx: c_int, depth: usize) -> Result<Value, String> {
+        if depth > MAX_DEPTH {
+            return Err("value is nested too deeply".to_owned());
+        }
+
+        unsafe {
+            let idx = ffi::lua_absindex(self.state, idx);
+
+            let value = match ffi::lua_type(self.state, idx) {
+                ffi::LUA_TNIL => Value::Null,
+                ffi::LUA_TBOOLEAN => Value::Bool(ffi::lua_toboolean(self.state, idx) != 0),
+                ffi::LUA_TNUMBER => number(ffi::lua_tonumberx(self.state, idx, ptr::null_mut())),
+                ffi::LUA_TSTRING => Value::String(self.to_string(idx)),
+                ffi::LUA_TTABLE => try!(self.read_table(idx, depth)),
+                ffi::LUA_TLIGHTUSERDATA if ffi::lua_touserdata(self.state, idx).is_null() => {
+                    Value::Null
+                }
+                ty => return Err(format!("unsupported value of type '{}'", self.typename(ty))),
+            };
+
+            Ok(value)
+        }
+    }
+
+    /// Reads the table at the given absolute stack index.
+    ///
+    /// Tables with keys forming `1..n` sequence are read as arrays, all other ones, including
+    /// empty, as objects.
+    unsafe fn read_table(&self, idx: c_int, depth: usize) -> Result<Value, String> {
+        if ffi::lua_checkstack(self.state, 3) == 0 {
+            return Err("stack overflow".to_owned());
+        }
+
+        let mut map = BTreeMap::new();
+        let mut items = BTreeMap::new();
+
+        ffi::lua_pushnil(self.state);
+
+        while ffi::lua_next(self.state, idx) != 0 {
+            let val = try!(self.read(-1, depth + 1));
+
+            // Numeric keys must not be converted in place, because it confuses the iteration.
+            match ffi::lua_type(self.state, -2) {
+                ffi::LUA_TNUMBER => {
+                    let key = ffi::lua_tonumberx(self.state, -2, ptr::null_mut());
+
+                    if key >= 1.0 && key.fract() == 0.0 {
+                        items.insert(key as usize, val);
+                    } else {
+                        map.insert(key.to_string(), val);
+                    }
+                }
+                ffi::LUA_TSTRING => {
+                    map.insert(self.to_string(-2), val);
+                }
+                ty => return Err(format!("unsupported key of type '{}'", self.typename(ty))),
+            }
+
+            ffi::lua_pop(self.state, 1);
+        }
+
+        if map.is_empty() && items.keys().next_back() == Some(&items.len()) {
+            Ok(Value::Array(items.into_iter().map(|(_, val)| val).collect()))
+        } else {
+            for (id, val) in items {
+                map.insert(id.to_string(), val);
+            }
+
+            Ok(Value::Object(map))
+        }
+    }
+}
+
+impl Drop for Lua {
+    fn drop(&mut self) {
+        unsafe { ffi::lua_close(self.state) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use std::time::Duration;
+
+    use serde_json::{self, Value};
+
+    use super::{number, Lua};
+
+    fn lua(code: &str) -> Lua {
+        let mut lua = Lua::new(1024 * 1024);
+        lua.load(code, Duration::from_secs(1)).unwrap();
+        lua
+    }
+
+    fn call(lua: &mut Lua, arg: &Value) -> Result<Vec<Value>, String> {
+        lua.call(&CString::new("test").unwrap(), arg, Duration::from_secs(1))
+    }
+
+    /// Returns the value of the given Lua expression.
+    fn eval(expr: &str) -> Value {
+        let mut lua = lua(&format!("function test() return {} end", expr));
+        call(&mut lua, &Value::Null).unwrap().remove(0)
+    }
+
+    fn json(val: &str) -> Value {
+        serde_json::from_str(val).unwrap()
+    }
+
+    #[test]
+    fn number_restores_integers() {
+        assert_eq!(Value::U64(42), number(42.0));
+        assert_eq!(Value::U64(0), number(0.0));
+        assert_eq!(Value::I64(-42), number(-42.0));
+        assert_eq!(Value::F64(1.5), number(1.5));
+        assert_eq!(Value::F64(-0.5), number(-0.5));
+        assert_eq!(Value::F64(9007199254740992.0), number(9007199254740992.0));
+    }
+
+    #[test]
+    fn read_sequence_as_array() {
+        assert_eq!(json("[1, \"two\", true]"), eval("{1, 'two', true}"));
+        assert_eq!(json("[[1], [2]]"), eval("{{1}, {2}}"));
+    }
+
+    #[test]
+    fn read_other_tables_as_objects() {
+        assert_eq!(json("{}"), eval("{}"));
+        assert_eq!(json("{\"a\": 1}"), eval("{a = 1}"));
+        assert_eq!(json("{\"1\": 1, \"3\": 3}"), eval("{[1] = 1, [3] = 3}"));
+        assert_eq!(json("{\"1\": 1, \"2\": 2, \"x\": 0}"), eval("{1, 2, x = 0}"));
+        assert_eq!(json("{\"0\": 0}"), eval("{[0] = 0}"));
+        assert_eq!(json("{\"1.5\": 1}"), eval("{[1.5] = 1}"));
+    }
+
+    #[test]
+    fn read_null() {
+        assert_eq!(Value::Null, eval("nil"));
+        assert_eq!(json("{\"a\": null}"), eval("{a = null}"));
+    }
+
+    #[test]
+    fn roundtrip() {
+        let record = json(r#"{"message": "hi", "pid": 42, "tags": ["a"], "ctx": {"x": null}}"#);
+
+        let mut lua = lua("function test(record) return record end");
+        assert_eq!(vec![record.clone()], call(&mut lua, &record).unwrap());
+    }
+
+    #[test]
+    fn sandbox() {
+        assert_eq!(json("[true, true, true, true]"),
+            eval("{os == nil, io == nil, require == nil, loadfile == nil}"));
+    }
+
+    #[test]
+    fn memory_limit() {
+        let mut lua = lua("function test() return string.rep('x', 1e8) end");
+        assert!(call(&mut lua, &Value::Null).is_err());
+    }
+
+    #[test]
+    fn memory_limit_on_argument() {
+        let mut lua = lua("function test(val) return #val end");
+
+        let huge = Value::String(::std::iter::repeat('x').take(2 * 1024 * 1024).collect());
+        assert!(call(&mut lua, &huge).is_err());
+
+        // The interpreter remains usable.
+        assert_eq!(vec![Value::U64(2)], call(&mut lua, &Value::String("xx".to_owned())).unwrap());
+    }
+}
@@ -0,0 +1,136 @@
+//! Filters transform the record stream between sources and outputs.
+//!
+//! Each pipeline runs its filters in the configured order in the pipeline processing thread, so
+//! filters must never block. A filter may drop a record, modify it, or produce several records
+//! from a single one.
+
+mod aggregate;
+mod config;
+mod decode;
+mod dedup;
+mod fields;
+mod grok;
+mod lookup;
+mod lua;
+mod multiline;
+pub mod pointer;
+mod ratelimit;
+mod redact;
+mod route;
+mod sample;
+mod schema;
+mod script;
+mod severity;
+mod trace;
+mod truncate;
+
+pub use self::aggregate::AggregateFilter;
+pub use self::decode::DecodeFilter;
+pub use self::dedup::DedupFilter;
+pub use self::fields::{FieldsFilter, HostnameFilter};
+pub use self::grok::GrokFilter;
+pub use self::lookup::LookupFilter;
+pub use self::multiline::MultilineFilter;
+pub use self::ratelimit::RateLimitFilter;
+pub use self::redact::RedactFilter;
+pub use self::route::RouteFilter;
+pub use self::sample::SampleFilter;
+pub use self::schema::SchemaFilter;
+pub use self::script::ScriptFilter;
+pub use self::severity::SeverityFilter;
+pub use self::trace::TraceFilter;
+pub use self::truncate::TruncateFilter;
+
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::time::Instant;
+
+use chrono::{Timelike, UTC};
+use serde_json::Value;
+
+use super::{Config, Record};
+use metrics::Scope;
+
+pub trait Filter: Send {
+    /// Processes the given record, pushing zero or more resulting records into `out`.
+    ///
+    /// Records are shared between outputs, so use `Arc::make_mut` for modification, which
+    /// clones the record only when necessary.
+    fn filter(&mut self, record: Arc<Record>, out: &mut Vec<Arc<Record>>);
+
+    /// Called periodically, about once per second, even if there are no records.
+    ///
+    /// Allows time-based filters to update their state and emit records on their own. Default
+    /// implementation does nothing.
+    fn tick(&mut self, _now: Instant, _out: &mut Vec<Arc<Record>>) {}
+
+    /// Called once on pipeline shutdown, after all records are processed.
+    ///
+    /// Filters, that hold records, must emit them here to avoid losing. Default implementation
+    /// does nothing.
+    fn flush(&mut self, _out: &mut Vec<Arc<Record>>) {}
+
+    /// Creates an optional sender, which is triggered on HUP signal.
+    ///
+    /// Filters, that depend on external files, should reload them on the next tick after the
+    /// event. Default implementation always returns None.
+    fn hup(&self) -> Option<Sender<()>> {
+        None
+    }
+}
+
+pub trait FilterFactory {
+    type Error: Into<Box<Error>>;
+
+    /// Returns type as a string that is used mainly for concrete factory identification.
+    fn ty() -> &'static str where Self: Sized;
+
+    /// Constructs the filter by configuring it with the given config.
+    ///
+    /// Relative file paths in the config should be resolved against the given base directory,
+    /// which is the directory of the main config file. The given metrics scope is already
+    /// labelled with both pipeline name and filter type.
+    fn from(cfg: &Config, pipeline: &str, base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+        where Self: Sized;
+}
+
+/// Sets the given record field, doing nothing if the record is not an object.
+///
+/// Existing field is left intact unless `overwrite` is set.
+fn set(record: &mut Arc<Record>, key: &str, value: Value, overwrite: bool) {
+    if !record.is_object() || (!overwrite && record.find(key).is_some()) {
+        return;
+    }
+
+    if let Some(map) = Arc::make_mut(record).as_object_mut() {
+        map.insert(key.to_owned(), value);
+    }
+}
+
+/// Appends the given tag to the record `tags` array, creating it if required.
+///
+/// Does nothing if the record is not an object or its `tags` field is not an array.
+fn tag(record: &mut Arc<Record>, tag: &str) {
+    match record.find("tags") {
+        Some(&Value::Array(..)) | None => {}
+        Some(..) => return,
+    }
+
+    if let Some(map) = Arc::make_mut(record).as_object_mut() {
+        let tags = map.entry("tags".to_owned()).or_insert_with(|| Value::Array(Vec::new()));
+
+        if let Value::Array(ref mut tags) = *tags {
+            tags.push(Value::String(tag.to_owned()));
+        }
+    }
+}
+
+/// Returns the current time in nanoseconds since the epoch, suitable for the `timestamp` field
+/// of records emitted by filters on their own.
+fn timestamp() -> i64 {
+    let now = UTC::now();
+    now.timestamp() * 1000000000 + now.nanosecond() as i64
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use serde_json::{self, Value};
+
+use {Config, Record};
+use config;
+use filter::{Filter, FilterFactory};
+use filter::config::MultilineConfig;
+use filter::pointer::Pointer;
+use metrics::{Counter, Scope};
+
+fn regex(pattern: Option<&str>, name: &str) -> Result<Option<Regex>, Box<Error>> {
+    match pattern {
+        Some(pattern) => {
+            let regex = try!(Regex::new(pattern)
+                .map_err(|err| format!("invalid '{}' pattern '{}': {}", name, pattern, err)));
+            Ok(Some(regex))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Record being merged.
+struct Pending {
+    /// The first record, which is emitted with the merged field.
+    record: Arc<Record>,
+    text: String,
+    lines: usize,
+    updated: Instant,
+}
+
+/// Merges continuation lines, like stack trace frames, into the preceding record.
+///
+/// Merged records are emitted when the next record of the same stream starts, when the limits
+/// are reached, when no continuation lines arrive within the timeout, and on pipeline shutdown.
+/// Records without the configured field are passed unchanged.
+pub struct MultilineFilter {
+    field: Pointer,
+    key: Vec<Pointer>,
+    start: Option<Regex>,
+    continuation: Option<Regex>,
+    indent: bool,
+    max_lines: usize,
+    max_bytes: usize,
+    timeout: Duration,
+    max_keys: usize,
+    pending: HashMap<String, Pending>,
+    merged: Counter,
+    overflows: Counter,
+}
+
+impl MultilineFilter {
+    fn new(cfg: MultilineConfig, metrics: &Scope) -> Result<MultilineFilter, Box<Error>> {
+        let filter = MultilineFilter {
+            field: cfg.field(),
+            key: cfg.key().clone(),
+            start: try!(regex(cfg.start(), "start")),
+            continuation: try!(regex(cfg.continuation(), "continue")),
+            indent: cfg.indent(),
+            max_lines: cfg.max_lines(),
+            max_bytes: cfg.max_bytes(),
+            timeout: cfg.timeout(),
+            max_keys: cfg.max_keys(),
+            pending: HashMap::new(),
+            merged: metrics.counter("zenlog_filter_merged_total",
+                "Number of continuation lines merged by the filter"),
+            overflows: metrics.counter("zenlog_filter_overflows_total",
+                "Number of records passed unmerged because of the streams limit"),
+        };
+
+        Ok(filter)
+    }
+
+    fn is_continuation(&self, line: &str) -> bool {
+        if let Some(ref regex) = self.continuation {
+            if regex.is_match(line) {
+                return true;
+            }
+        }
+
+        if let Some(ref regex) = self.start {
+            if !regex.is_match(line) {
+                return true;
+            }
+        }
+
+        self.indent && line.starts_with(|ch: char| ch == ' ' || ch == '\t')
+    }
+
+    fn emit(&self, pending: Pending, out: &mut Vec<Arc<Record>>) {
+        let mut record = pending.record;
+
+        if pending.lines > 1 {
+            self.field.insert(Arc::make_mut(&mut record), Value::String(pending.text));
+        }
+
+        out.push(record);
+    }
+}
+
+impl Filter for MultilineFilter {
+    fn filter(&mut self, record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        let line = match self.field.find(&record).and_then(|val| val.as_string()) {
+            Some(line) => line.to_owned(),
+            None => {
+                out.push(record);
+                return;
+            }
+        };
+
+        let id = {
+            let key: Vec<Option<&Value>> = self.key.iter().map(|path| path.find(&record)).collect();
+            serde_json::to_string(&key).unwrap_or_else(|_| String::new())
+        };
+
+        let continuation = self.is_continuation(&line);
+
+        if continuation {
+            if let Some(pending) = self.pending.get_mut(&id) {
+                let fits = pending.lines < self.max_lines &&
+                    pending.text.len() + 1 + line.len() <= self.max_bytes;
+
+                if fits {
+                    pending.text.push('\n');
+                    pending.text.push_str(&line);
+                    pending.lines += 1;
+                    pending.updated = Instant::now();
+                    self.merged.inc();
+                    return;
+                }
+            }
+        }
+
+        match self.pending.remove(&id) {
+            Some(pending) => self.emit(pending, out),
+            None => {
+                // Protect from unbounded memory growth on high-cardinality keys.
+                if self.pending.len() >= self.max_keys {
+                    self.overflows.inc();
+                    out.push(record);
+                    return;
+                }
+            }
+        }
+
+        let pending = Pending {
+            record: record,
+            text: line,
+            lines: 1,
+            updated: Instant::now(),
+        };
+
+        self.pending.insert(id, pending);
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Arc<Record>>) {
+        let timeout = self.timeout;
+        let expired: Vec<String> = self.pending.iter()
+            .filter(|&(_, pending)| now.duration_since(pending.updated) >= timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in expired {
+            if let Some(pending) = self.pending.remove(&id) {
+                self.emit(pending, out);
+            }
+        }
+    }
+
+    fn flush(&mut self, out: &mut Vec<Arc<Record>>) {
+        let pending: Vec<Pending> = self.pending.drain().map(|(_, pending)| pending).collect();
+
+        for pending in pending {
+            self.emit(pending, out);
+        }
+    }
+}
+
+impl FilterFactory for MultilineFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "multiline"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: MultilineConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(try!(MultilineFilter::new(cfg, metrics))))
+    }
+}
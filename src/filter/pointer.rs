@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde_json::Value;
+
+/// JSON-pointer-style path to a record field, i.e. `/request/headers/host`.
+///
+/// The leading slash is optional, so `message` and `/message` are the same. As in RFC 6901 `~1`
+/// and `~0` are unescaped into `/` and `~` respectively. Numeric segments index arrays.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pointer {
+    segments: Vec<String>,
+}
+
+impl Pointer {
+    /// Returns the pointer to the top-level field with the given name, which is used as is.
+    pub fn field(name: &str) -> Pointer {
+        Pointer { segments: vec![name.to_owned()] }
+    }
+
+    /// Returns the pointer to the sibling field with the given name.
+    pub fn sibling(&self, name: &str) -> Pointer {
+        let mut segments = self.segments.clone();
+        segments.pop();
+        segments.push(name.to_owned());
+
+        Pointer { segments: segments }
+    }
+
+    /// Returns whether either of pointers refers to the other one or to its nested field.
+    pub fn overlaps(&self, other: &Pointer) -> bool {
+        self.segments.iter().zip(other.segments.iter()).all(|(lhs, rhs)| lhs == rhs)
+    }
+
+    pub fn find<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        let mut value = value;
+
+        for segment in &self.segments {
+            let next = match *value {
+                Value::Object(ref map) => map.get(segment),
+                Value::Array(ref vec) => segment.parse().ok().and_then(|id: usize| vec.get(id)),
+                _ => None,
+            };
+
+            value = match next {
+                Some(next) => next,
+                None => return None,
+            };
+        }
+
+        Some(value)
+    }
+
+    pub fn find_mut<'a>(&self, value: &'a mut Value) -> Option<&'a mut Value> {
+        Pointer::lookup_mut(value, &self.segments)
+    }
+
+    fn lookup_mut<'a>(value: &'a mut Value, segments: &[String]) -> Option<&'a mut Value> {
+        let mut value = value;
+
+        for segment in segments {
+            let current = value;
+
+            value = match *current {
+                Value::Object(ref mut map) => {
+                    match map.get_mut(segment) {
+                        Some(next) => next,
+                        None => return None,
+                    }
+                }
+                Value::Array(ref mut vec) => {
+                    match segment.parse().ok().and_then(move |id: usize| vec.get_mut(id)) {
+                        Some(next) => next,
+                        None => return None,
+                    }
+                }
+                _ => return None,
+            };
+        }
+
+        Some(value)
+    }
+
+    /// Removes the field, returning its value if it existed.
+    pub fn remove(&self, value: &mut Value) -> Option<Value> {
+        let (last, parent) = self.segments.split_last().expect("pointer must not be empty");
+
+        match Pointer::lookup_mut(value, parent) {
+            Some(&mut Value::Object(ref mut map)) => map.remove(last),
+            Some(&mut Value::Array(ref mut vec)) => {
+                match last.parse() {
+                    Ok(id) if id < vec.len() => Some(vec.remove(id)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns whether the field can be set, i.e. no intermediate field exists, that is not an
+    /// object.
+    pub fn can_insert(&self, value: &Value) -> bool {
+        let (_, parent) = self.segments.split_last().expect("pointer must not be empty");
+        let mut value = value;
+
+        for segment in parent {
+            value = match *value {
+                Value::Object(ref map) => {
+                    match map.get(segment) {
+                        Some(next) => next,
+                        None => return true,
+                    }
+                }
+                _ => return false,
+            };
+        }
+
+        value.is_object()
+    }
+
+    /// Sets the field, creating intermediate objects if required.
+    ///
+    /// Returns false if some intermediate field exists, but is not an object.
+    pub fn insert(&self, value: &mut Value, field: Value) -> bool {
+        let (last, parent) = self.segments.split_last().expect("pointer must not be empty");
+        let mut value = value;
+
+        for segment in parent {
+            let current = value;
+
+            value = match *current {
+                Value::Object(ref mut map) => {
+                    map.entry(segment.clone()).or_insert_with(|| Value::Object(BTreeMap::new()))
+                }
+                _ => return false,
+            };
+        }
+
+        match *value {
+            Value::Object(ref mut map) => {
+                map.insert(last.clone(), field);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Display for Pointer {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        for segment in &self.segments {
+            try!(write!(fmt, "/{}", segment.replace("~", "~0").replace("/", "~1")));
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Pointer {
+    type Err = String;
+
+    fn from_str(path: &str) -> Result<Pointer, String> {
+        let trimmed = if path.starts_with('/') { &path[1..] } else { path };
+
+        if trimmed.is_empty() {
+            return Err(format!("invalid field path '{}': must not be empty", path));
+        }
+
+        let segments = trimmed.split('/')
+            .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+            .collect();
+
+        Ok(Pointer { segments: segments })
+    }
+}
+
+impl Deserialize for Pointer {
+    fn deserialize<D>(de: &mut D) -> Result<Pointer, D::Error>
+        where D: Deserializer
+    {
+        let path = try!(String::deserialize(de));
+        path.parse().map_err(de::Error::custom)
+    }
+}
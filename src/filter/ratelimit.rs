@@ -0,0 +1,185 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::{self, Value};
+
+use {Config, Record};
+use config;
+use filter::{self, Filter, FilterFactory};
+use filter::config::RateLimitConfig;
+use filter::pointer::Pointer;
+use metrics::{Counter, Scope};
+use severity::Severity;
+
+fn as_secs(val: Duration) -> f64 {
+    val.as_secs() as f64 + val.subsec_nanos() as f64 / 1e9
+}
+
+/// Token bucket.
+struct Bucket {
+    /// Key field values, used for summary records.
+    key: Vec<Value>,
+    tokens: f64,
+    updated: Instant,
+    /// Number of records suppressed since the last summary.
+    suppressed: u64,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant, rate: f64, burst: f64) {
+        if now > self.updated {
+            self.tokens = (self.tokens + as_secs(now - self.updated) * rate).min(burst);
+            self.updated = now;
+        }
+    }
+}
+
+/// Limits the rate of records using token buckets, keyed by the given field combination.
+///
+/// Periodically emits a summary record for each bucket that suppressed records since the
+/// previous summary.
+pub struct RateLimitFilter {
+    key: Vec<Pointer>,
+    rate: f64,
+    burst: f64,
+    summary: Option<Duration>,
+    summarized: Instant,
+    max_keys: usize,
+    buckets: HashMap<String, Bucket>,
+    suppressed: Counter,
+}
+
+impl RateLimitFilter {
+    fn new(cfg: RateLimitConfig, metrics: &Scope) -> Result<RateLimitFilter, Box<Error>> {
+        if cfg.rate() <= 0.0 {
+            return Err("rate must be positive".into());
+        }
+
+        if cfg.burst() < 1.0 {
+            return Err("burst must be at least 1".into());
+        }
+
+        let filter = RateLimitFilter {
+            key: cfg.key().clone(),
+            rate: cfg.rate(),
+            burst: cfg.burst(),
+            summary: cfg.summary(),
+            summarized: Instant::now(),
+            max_keys: cfg.max_keys(),
+            buckets: HashMap::new(),
+            suppressed: metrics.counter("zenlog_filter_suppressed_total",
+                "Number of records suppressed by the filter"),
+        };
+
+        Ok(filter)
+    }
+
+    fn summarize(&self, bucket: &Bucket, interval: Duration) -> Record {
+        let mut key = BTreeMap::new();
+        for (path, val) in self.key.iter().zip(bucket.key.iter()) {
+            key.insert(path.to_string().trim_left_matches('/').to_owned(), val.clone());
+        }
+
+        let mut ratelimit = BTreeMap::new();
+        ratelimit.insert("key".to_owned(), Value::Object(key));
+        ratelimit.insert("suppressed".to_owned(), Value::U64(bucket.suppressed));
+
+        let mut record = BTreeMap::new();
+        record.insert("message".to_owned(), Value::String(format!(
+            "suppressed {} record(s) in the last {}s", bucket.suppressed, interval.as_secs())));
+        record.insert("severity".to_owned(), Value::String(Severity::Warn.as_str().to_owned()));
+        record.insert("timestamp".to_owned(), Value::I64(filter::timestamp()));
+        record.insert("ratelimit".to_owned(), Value::Object(ratelimit));
+
+        Value::Object(record)
+    }
+}
+
+impl Filter for RateLimitFilter {
+    fn filter(&mut self, record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        let key: Vec<Value> = self.key.iter()
+            .map(|path| path.find(&record).cloned().unwrap_or(Value::Null))
+            .collect();
+        let id = serde_json::to_string(&key).unwrap_or_else(|_| String::new());
+
+        // Protect from unbounded memory growth on high-cardinality keys.
+        if !self.buckets.contains_key(&id) && self.buckets.len() >= self.max_keys {
+            out.push(record);
+            return;
+        }
+
+        let now = Instant::now();
+        let burst = self.burst;
+
+        let bucket = self.buckets.entry(id).or_insert_with(move || {
+            Bucket {
+                key: key,
+                tokens: burst,
+                updated: now,
+                suppressed: 0,
+            }
+        });
+
+        bucket.refill(now, self.rate, self.burst);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            out.push(record);
+        } else {
+            bucket.suppressed += 1;
+            self.suppressed.inc();
+        }
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Arc<Record>>) {
+        if let Some(interval) = self.summary {
+            if now.duration_since(self.summarized) >= interval {
+                self.summarized = now;
+
+                for bucket in self.buckets.values().filter(|bucket| bucket.suppressed > 0) {
+                    out.push(Arc::new(self.summarize(bucket, interval)));
+                }
+
+                for bucket in self.buckets.values_mut() {
+                    bucket.suppressed = 0;
+                }
+            }
+        }
+
+        // Forget buckets, that are full again, because they behave exactly like new ones.
+        let (rate, burst) = (self.rate, self.burst);
+        let summary = self.summary.is_some();
+        let mut idle = Vec::new();
+
+        for (id, bucket) in &mut self.buckets {
+            bucket.refill(now, rate, burst);
+
+            if bucket.tokens >= burst && (bucket.suppressed == 0 || !summary) {
+                idle.push(id.clone());
+            }
+        }
+
+        for id in idle {
+            self.buckets.remove(&id);
+        }
+    }
+}
+
+impl FilterFactory for RateLimitFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "ratelimit"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: RateLimitConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(try!(RateLimitFilter::new(cfg, metrics))))
+    }
+}
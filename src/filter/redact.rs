@@ -0,0 +1,470 @@
+use std::cmp;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use regex::Regex;
+use serde_json::Value;
+
+use {Config, Record};
+use config;
+use filter::{Filter, FilterFactory};
+use filter::config::{Action, RedactConfig};
+use filter::pointer::Pointer;
+use metrics::{Counter, Scope};
+
+/// Name of the group, that limits the redacted part of a match.
+const SECRET_GROUP: &'static str = "secret";
+
+/// Number of hash bytes kept in redacted values.
+const HASH_LEN: usize = 16;
+
+/// Built-in detectors.
+const DETECTORS: &'static [(&'static str, &'static str)] = &[
+    ("email", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}"),
+    ("ipv4", concat!(r"\b(?:(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\.){3}",
+        r"(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\b")),
+    ("ipv6", concat!(r"\b(?:[0-9A-Fa-f]{1,4}:){7}[0-9A-Fa-f]{1,4}\b",
+        r"|\b(?:[0-9A-Fa-f]{1,4}:){1,6}:(?:[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){0,5})?\b")),
+    ("credit_card", r"\b(?:[0-9][ -]?){12,18}[0-9]\b"),
+    ("bearer", r"(?i)\bbearer\s+(?P<secret>[A-Za-z0-9\-._~+/]+=*)"),
+];
+
+/// Validates the card number checksum, ignoring separators.
+fn luhn(val: &str) -> bool {
+    let digits: Vec<u32> = val.chars().filter_map(|ch| ch.to_digit(10)).collect();
+
+    if digits.len() < 13 || digits.len() > 19 {
+        return false;
+    }
+
+    let sum = digits.iter().rev().enumerate().fold(0, |sum, (id, &digit)| {
+        if id % 2 == 1 {
+            let digit = digit * 2;
+            sum + if digit > 9 { digit - 9 } else { digit }
+        } else {
+            sum + digit
+        }
+    });
+
+    sum % 10 == 0
+}
+
+/// Returns byte ranges of card numbers within the given match, that pass the Luhn check.
+///
+/// The pattern is greedy, so it may swallow adjacent digits, i.e. "4111 1111 1111 1111 5".
+/// Therefore runs of whole digit groups are checked as well, preferring the leftmost longest.
+fn cards(text: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    // Separators are single ASCII characters, so groups are never empty.
+    let mut groups = Vec::new();
+    let mut from = start;
+
+    for (pos, ch) in text[start..end].char_indices() {
+        if !ch.is_digit(10) {
+            groups.push((from, start + pos));
+            from = start + pos + 1;
+        }
+    }
+
+    groups.push((from, end));
+
+    let mut result = Vec::new();
+    let mut first = 0;
+
+    while first < groups.len() {
+        let last = (first..groups.len()).rev()
+            .find(|&last| luhn(&text[groups[first].0..groups[last].1]));
+
+        match last {
+            Some(last) => {
+                result.push((groups[first].0, groups[last].1));
+                first = last + 1;
+            }
+            None => first += 1,
+        }
+    }
+
+    result
+}
+
+struct Detector {
+    name: String,
+    regex: Regex,
+    /// Group to redact, either the secret one or the whole match.
+    group: usize,
+    /// Whether matches must pass the Luhn check.
+    luhn: bool,
+}
+
+impl Detector {
+    fn new(name: &str, pattern: &str) -> Result<Detector, Box<Error>> {
+        let regex = try!(Regex::new(pattern)
+            .map_err(|err| format!("invalid '{}' detector pattern: {}", name, err)));
+        let group = regex.capture_names()
+            .position(|group| group == Some(SECRET_GROUP))
+            .unwrap_or(0);
+
+        let detector = Detector {
+            name: name.to_owned(),
+            regex: regex,
+            group: group,
+            luhn: name == "credit_card",
+        };
+
+        Ok(detector)
+    }
+
+    /// Appends byte ranges of sensitive data in the given text.
+    fn find(&self, id: usize, text: &str, ranges: &mut Vec<(usize, usize, usize)>) {
+        for caps in self.regex.captures_iter(text) {
+            if let Some((start, end)) = caps.pos(self.group) {
+                if !self.luhn {
+                    ranges.push((start, end, id));
+                    continue;
+                }
+
+                for (start, end) in cards(text, start, end) {
+                    ranges.push((start, end, id));
+                }
+            }
+        }
+    }
+}
+
+/// Redacts sensitive data, like emails, card numbers or tokens, from string fields.
+pub struct RedactFilter {
+    fields: Option<Vec<Pointer>>,
+    detectors: Vec<Detector>,
+    action: Action,
+    mask: String,
+    key: Vec<u8>,
+    redacted: Counter,
+}
+
+impl RedactFilter {
+    fn new(cfg: RedactConfig, metrics: &Scope) -> Result<RedactFilter, Box<Error>> {
+        let mut detectors = Vec::new();
+
+        match cfg.detectors() {
+            Some(names) => {
+                for name in names {
+                    let pattern = try!(DETECTORS.iter()
+                        .find(|&&(key, _)| key == name.as_str())
+                        .map(|&(_, pattern)| pattern)
+                        .ok_or(format!("unknown detector '{}'", name)));
+                    detectors.push(try!(Detector::new(name, pattern)));
+                }
+            }
+            None => {
+                for &(name, pattern) in DETECTORS {
+                    detectors.push(try!(Detector::new(name, pattern)));
+                }
+            }
+        }
+
+        for (name, pattern) in cfg.patterns() {
+            detectors.push(try!(Detector::new(name, pattern)));
+        }
+
+        if cfg.action() == Action::Drop && cfg.fields().is_none() {
+            return Err("'fields' are required for 'drop' action".into());
+        }
+
+        let key = match (cfg.action(), cfg.key()) {
+            (Action::Hash, None) => return Err("'key' is required for 'hash' action".into()),
+            (_, key) => key.unwrap_or("").as_bytes().to_vec(),
+        };
+
+        let filter = RedactFilter {
+            fields: cfg.fields().cloned(),
+            detectors: detectors,
+            action: cfg.action(),
+            mask: cfg.mask().to_owned(),
+            key: key,
+            redacted: metrics.counter("zenlog_filter_redacted_total",
+                "Number of records redacted by the filter"),
+        };
+
+        Ok(filter)
+    }
+
+    /// Returns sorted non-overlapping ranges of sensitive data with detector indices.
+    ///
+    /// Overlapping matches are merged, keeping the detector of the first one, so no part of any
+    /// match is left in clear text.
+    fn ranges(&self, text: &str) -> Vec<(usize, usize, usize)> {
+        let mut ranges = Vec::new();
+
+        for (id, detector) in self.detectors.iter().enumerate() {
+            detector.find(id, text, &mut ranges);
+        }
+
+        ranges.sort();
+
+        let mut merged: Vec<(usize, usize, usize)> = Vec::with_capacity(ranges.len());
+        for (start, end, id) in ranges {
+            if let Some(last) = merged.last_mut() {
+                if start < last.1 {
+                    last.1 = cmp::max(last.1, end);
+                    continue;
+                }
+            }
+
+            merged.push((start, end, id));
+        }
+
+        merged
+    }
+
+    fn hash(&self, val: &str) -> String {
+        let mut mac = Hmac::new(Sha256::new(), &self.key);
+        mac.input(val.as_bytes());
+
+        mac.result().code()[..HASH_LEN].iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Returns the redacted text, if there is anything to redact.
+    fn redact(&self, text: &str) -> Option<String> {
+        let ranges = self.ranges(text);
+
+        if ranges.is_empty() {
+            return None;
+        }
+
+        let mut result = String::with_capacity(text.len());
+        let mut pos = 0;
+
+        for (start, end, id) in ranges {
+            result.push_str(&text[pos..start]);
+
+            match self.action {
+                Action::Hash => {
+                    let hash = self.hash(&text[start..end]);
+                    result.push_str(&format!("[{}:{}]", self.detectors[id].name, hash));
+                }
+                Action::Mask | Action::Drop => result.push_str(&self.mask),
+            }
+
+            pos = end;
+        }
+
+        result.push_str(&text[pos..]);
+
+        Some(result)
+    }
+
+    /// Returns whether the given value contains anything to redact.
+    fn affected(&self, value: &Value) -> bool {
+        match *value {
+            Value::String(ref val) => !self.ranges(val).is_empty(),
+            Value::Array(ref vec) => vec.iter().any(|val| self.affected(val)),
+            Value::Object(ref map) => map.values().any(|val| self.affected(val)),
+            _ => false,
+        }
+    }
+
+    /// Redacts the given value recursively, returning whether it must be removed.
+    fn walk(&self, value: &mut Value) -> bool {
+        match *value {
+            Value::String(ref mut val) => {
+                if let Some(redacted) = self.redact(val) {
+                    if self.action == Action::Drop {
+                        return true;
+                    }
+
+                    *val = redacted;
+                }
+            }
+            Value::Array(ref mut vec) => {
+                let mut id = 0;
+                while id < vec.len() {
+                    if self.walk(&mut vec[id]) {
+                        vec.remove(id);
+                    } else {
+                        id += 1;
+                    }
+                }
+            }
+            Value::Object(ref mut map) => {
+                let removed: Vec<String> = map.iter_mut()
+                    .filter_map(|(key, val)| if self.walk(val) { Some(key.clone()) } else { None })
+                    .collect();
+
+                for key in removed {
+                    map.remove(&key);
+                }
+            }
+            _ => {}
+        }
+
+        false
+    }
+
+    fn apply(&self, record: &mut Record) {
+        match self.fields {
+            Some(ref fields) => {
+                for field in fields {
+                    let removed = match field.find_mut(record) {
+                        Some(val) => self.walk(val),
+                        None => false,
+                    };
+
+                    if removed {
+                        field.remove(record);
+                    }
+                }
+            }
+            None => {
+                self.walk(record);
+            }
+        }
+    }
+}
+
+impl Filter for RedactFilter {
+    fn filter(&mut self, mut record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        let affected = match self.fields {
+            Some(ref fields) => {
+                fields.iter()
+                    .any(|field| field.find(&record).map_or(false, |val| self.affected(val)))
+            }
+            None => self.affected(&record),
+        };
+
+        if affected {
+            self.redacted.inc();
+            self.apply(Arc::make_mut(&mut record));
+        }
+
+        out.push(record);
+    }
+}
+
+impl FilterFactory for RedactFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "redact"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: RedactConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(try!(RedactFilter::new(cfg, metrics))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use metrics::Counter;
+    use filter::config::Action;
+
+    use super::{luhn, Detector, RedactFilter, DETECTORS};
+
+    fn detector(name: &str) -> Detector {
+        let &(_, pattern) = DETECTORS.iter().find(|&&(key, _)| key == name).unwrap();
+        Detector::new(name, pattern).unwrap()
+    }
+
+    fn detect(name: &str, text: &str) -> Vec<String> {
+        let mut ranges = Vec::new();
+        detector(name).find(0, text, &mut ranges);
+
+        ranges.into_iter().map(|(start, end, _)| text[start..end].to_owned()).collect()
+    }
+
+    fn filter(names: &[&str]) -> RedactFilter {
+        RedactFilter {
+            fields: None,
+            detectors: names.iter().map(|name| detector(name)).collect(),
+            action: Action::Mask,
+            mask: "***".to_owned(),
+            key: Vec::new(),
+            redacted: Counter::default(),
+        }
+    }
+
+    #[test]
+    fn luhn_valid() {
+        assert!(luhn("4111111111111111"));
+        assert!(luhn("4111 1111 1111 1111"));
+        assert!(luhn("4111-1111-1111-1111"));
+        assert!(luhn("5500 0000 0000 0004"));
+    }
+
+    #[test]
+    fn luhn_invalid() {
+        assert!(!luhn("4111111111111112"));
+        assert!(!luhn("1234"));
+        assert!(!luhn("41111111111111111111"));
+    }
+
+    #[test]
+    fn detect_email() {
+        assert_eq!(vec!["john.doe+x@example.co.uk"],
+            detect("email", "contact john.doe+x@example.co.uk now"));
+        assert!(detect("email", "user@localhost").is_empty());
+    }
+
+    #[test]
+    fn detect_ipv4() {
+        assert_eq!(vec!["192.168.1.10"], detect("ipv4", "from 192.168.1.10:80"));
+        assert!(detect("ipv4", "999.1.1.1").is_empty());
+        assert!(detect("ipv4", "version 1.2.3").is_empty());
+    }
+
+    #[test]
+    fn detect_ipv6() {
+        assert_eq!(vec!["2001:0db8:85a3:0000:0000:8a2e:0370:7334"],
+            detect("ipv6", "addr 2001:0db8:85a3:0000:0000:8a2e:0370:7334 up"));
+        assert_eq!(vec!["fe80::1"], detect("ipv6", "link fe80::1 up"));
+    }
+
+    #[test]
+    fn detect_credit_card() {
+        assert_eq!(vec!["4111 1111 1111 1111"],
+            detect("credit_card", "card 4111 1111 1111 1111 used"));
+        assert!(detect("credit_card", "card 4111 1111 1111 1112 used").is_empty());
+        assert!(detect("credit_card", "order 123456").is_empty());
+    }
+
+    #[test]
+    fn detect_credit_card_with_adjacent_digits() {
+        assert_eq!(vec!["4111 1111 1111 1111"],
+            detect("credit_card", "card 4111 1111 1111 1111 5 used"));
+        assert_eq!(vec!["4111-1111-1111-1111"],
+            detect("credit_card", "card 5-4111-1111-1111-1111 used"));
+        assert!(detect("credit_card", "card 4111 1111 1111 1112 5 used").is_empty());
+    }
+
+    #[test]
+    fn detect_bearer() {
+        assert_eq!(vec!["abc.def-123=="],
+            detect("bearer", "Authorization: Bearer abc.def-123=="));
+        assert_eq!(vec!["token"], detect("bearer", "bearer token"));
+        assert!(detect("bearer", "bearer").is_empty());
+    }
+
+    #[test]
+    fn redact_merges_overlapping_matches() {
+        let filter = filter(&["ipv4", "email"]);
+
+        assert_eq!(Some("login *** failed".to_owned()),
+            filter.redact("login 10.0.0.1@corp.com failed"));
+    }
+
+    #[test]
+    fn redact_keeps_disjoint_matches() {
+        let filter = filter(&["ipv4", "email"]);
+
+        assert_eq!(Some("*** and ***".to_owned()), filter.redact("10.0.0.1 and a@b.com"));
+        assert_eq!(None, filter.redact("nothing here"));
+    }
+}
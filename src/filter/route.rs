@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use {Config, Record};
+use config;
+use filter::{Filter, FilterFactory};
+use filter::config::RouteConfig;
+use filter::pointer::Pointer;
+use metrics::Scope;
+
+/// Reshapes records by renaming, moving, keeping and dropping fields.
+///
+/// Operations are applied in the following order: rename, move, keep, drop. Thus `keep` and
+/// `drop` lists should refer to the resulting field names. Missing fields are silently ignored.
+///
+/// Renames and moves are configured as maps, which have no order, so their sources and
+/// destinations must not overlap.
+pub struct RouteFilter {
+    moves: Vec<(Pointer, Pointer)>,
+    keep: Option<Vec<Pointer>>,
+    drop: Vec<Pointer>,
+}
+
+impl RouteFilter {
+    fn new(cfg: RouteConfig) -> Result<RouteFilter, Box<Error>> {
+        let mut moves = Vec::new();
+
+        // Renaming is just a move within the same parent.
+        for (path, name) in cfg.rename() {
+            if name.is_empty() || name.contains('/') {
+                return Err(format!("invalid new name '{}' for '{}'", name, path).into());
+            }
+
+            let src: Pointer = try!(path.parse());
+            let dst = src.sibling(name);
+            moves.push((src, dst));
+        }
+
+        for (src, dst) in cfg.moves() {
+            moves.push((try!(src.parse()), try!(dst.parse())));
+        }
+
+        for (id, &(_, ref dst)) in moves.iter().enumerate() {
+            for (other, &(ref src, ref dst2)) in moves.iter().enumerate() {
+                if id == other {
+                    continue;
+                }
+
+                if dst.overlaps(src) {
+                    return Err(format!("'{}' is both moved to and from, chained renames and moves \
+                        are not supported", dst).into());
+                }
+
+                if dst.overlaps(dst2) {
+                    return Err(format!("conflicting destinations '{}' and '{}'", dst, dst2)
+                        .into());
+                }
+            }
+        }
+
+        let filter = RouteFilter {
+            moves: moves,
+            keep: try!(cfg.keep()).cloned(),
+            drop: cfg.drop().clone(),
+        };
+
+        Ok(filter)
+    }
+}
+
+impl Filter for RouteFilter {
+    fn filter(&mut self, mut record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        if !record.is_object() {
+            out.push(record);
+            return;
+        }
+
+        {
+            let record = Arc::make_mut(&mut record);
+
+            // Values are left in place if their destination can't hold them, rather than lost.
+            for &(ref src, ref dst) in &self.moves {
+                if !dst.can_insert(record) {
+                    continue;
+                }
+
+                if let Some(value) = src.remove(record) {
+                    dst.insert(record, value);
+                }
+            }
+
+            if let Some(ref keep) = self.keep {
+                let mut src = mem::replace(record, Value::Object(BTreeMap::new()));
+
+                for path in keep {
+                    if !path.can_insert(record) {
+                        continue;
+                    }
+
+                    if let Some(value) = path.remove(&mut src) {
+                        path.insert(record, value);
+                    }
+                }
+            }
+
+            for path in &self.drop {
+                path.remove(record);
+            }
+        }
+
+        out.push(record);
+    }
+}
+
+impl FilterFactory for RouteFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "route"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, _metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: RouteConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(try!(RouteFilter::new(cfg))))
+    }
+}
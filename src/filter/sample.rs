@@ -0,0 +1,109 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{self, Value};
+
+use {Config, Record};
+use config;
+use filter::{Filter, FilterFactory};
+use filter::config::SampleConfig;
+use filter::pointer::Pointer;
+use metrics::{Counter, Scope};
+
+/// FNV-1a hash, which is stable across runs and hosts unlike the standard one.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Maps the given number uniformly into [0; 1) range.
+fn unit(val: u64) -> f64 {
+    (val >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Keeps only the configured fraction of records.
+///
+/// Sampling is either random or deterministic, based on the key field value hash, which allows
+/// to keep or drop related records, like all spans of a single trace, together.
+pub struct SampleFilter {
+    rate: f64,
+    key: Option<Pointer>,
+    /// Xorshift random generator state, which is never zero.
+    state: u64,
+    suppressed: Counter,
+}
+
+impl SampleFilter {
+    fn new(cfg: SampleConfig, metrics: &Scope) -> Result<SampleFilter, Box<Error>> {
+        if cfg.rate() <= 0.0 || cfg.rate() > 1.0 {
+            return Err(format!("rate must be in (0; 1] range, got {}", cfg.rate()).into());
+        }
+
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|val| val.as_secs() ^ val.subsec_nanos() as u64)
+            .unwrap_or(0);
+
+        let filter = SampleFilter {
+            rate: cfg.rate(),
+            key: cfg.key().cloned(),
+            state: seed | 1,
+            suppressed: metrics.counter("zenlog_filter_suppressed_total",
+                "Number of records suppressed by the filter"),
+        };
+
+        Ok(filter)
+    }
+
+    fn random(&mut self) -> f64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        unit(self.state.wrapping_mul(0x2545f4914f6cdd1d))
+    }
+
+    fn hash(val: &Value) -> f64 {
+        let hash = match *val {
+            Value::String(ref val) => fnv1a(val.as_bytes()),
+            ref val => {
+                fnv1a(serde_json::to_string(val).unwrap_or_else(|_| String::new()).as_bytes())
+            }
+        };
+
+        unit(hash)
+    }
+}
+
+impl Filter for SampleFilter {
+    fn filter(&mut self, record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        let val = match self.key.as_ref().and_then(|key| key.find(&record)) {
+            Some(val) => SampleFilter::hash(val),
+            None => self.random(),
+        };
+
+        if val < self.rate {
+            out.push(record);
+        } else {
+            self.suppressed.inc();
+        }
+    }
+}
+
+impl FilterFactory for SampleFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "sample"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: SampleConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(try!(SampleFilter::new(cfg, metrics))))
+    }
+}
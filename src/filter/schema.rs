@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use {Config, Record};
+use config;
+use filter::{self, Filter, FilterFactory};
+use filter::config::{FieldSchema, FieldType, OnInvalid, SchemaConfig};
+use metrics::{Counter, Scope};
+use severity::Severity;
+
+/// Tag appended to invalid records with `annotate` policy.
+const INVALID_TAG: &'static str = "_schemafailure";
+
+/// Field with validation errors of annotated records.
+const ERRORS_FIELD: &'static str = "_schemaerrors";
+
+/// Maximum absolute value of integers, that are exactly representable as floats.
+const MAX_EXACT_INT: f64 = 9007199254740992.0;
+
+fn kind(value: &Value) -> &'static str {
+    match *value {
+        Value::Null => "null",
+        Value::Bool(..) => "boolean",
+        Value::I64(..) | Value::U64(..) => "integer",
+        Value::F64(..) => "number",
+        Value::String(..) => "string",
+        Value::Array(..) => "array",
+        Value::Object(..) => "object",
+    }
+}
+
+fn matches(ty: FieldType, value: &Value) -> bool {
+    match (ty, value) {
+        (FieldType::String, &Value::String(..)) |
+        (FieldType::Integer, &Value::I64(..)) |
+        (FieldType::Integer, &Value::U64(..)) |
+        (FieldType::Number, &Value::I64(..)) |
+        (FieldType::Number, &Value::U64(..)) |
+        (FieldType::Number, &Value::F64(..)) |
+        (FieldType::Boolean, &Value::Bool(..)) |
+        (FieldType::Object, &Value::Object(..)) |
+        (FieldType::Array, &Value::Array(..)) => true,
+        _ => false,
+    }
+}
+
+/// Parses the integer, preferring unsigned representation.
+fn integer(val: &str) -> Option<Value> {
+    val.parse().map(Value::U64).or_else(|_| val.parse().map(Value::I64)).ok()
+}
+
+/// Converts the value into the given type, if it can be done without losing information.
+///
+/// Non-negative integers are converted into unsigned ones, like the JSON decoder does.
+fn coerce(ty: FieldType, value: &Value) -> Option<Value> {
+    match (ty, value) {
+        (FieldType::Integer, &Value::String(ref val)) => integer(val.trim()),
+        (FieldType::Integer, &Value::F64(val))
+            if val.fract() == 0.0 && val.abs() < MAX_EXACT_INT =>
+        {
+            if val < 0.0 {
+                Some(Value::I64(val as i64))
+            } else {
+                Some(Value::U64(val as u64))
+            }
+        }
+        (FieldType::Number, &Value::String(ref val)) => {
+            // Integers are kept exact, because floats can't represent them beyond 2^53.
+            let val = val.trim();
+            integer(val).or_else(|| {
+                match val.parse::<f64>() {
+                    Ok(val) if val.is_finite() => Some(Value::F64(val)),
+                    _ => None,
+                }
+            })
+        }
+        (FieldType::String, &Value::I64(val)) => Some(Value::String(val.to_string())),
+        (FieldType::String, &Value::U64(val)) => Some(Value::String(val.to_string())),
+        (FieldType::String, &Value::F64(val)) => Some(Value::String(val.to_string())),
+        (FieldType::String, &Value::Bool(val)) => Some(Value::String(val.to_string())),
+        (FieldType::Boolean, &Value::String(ref val)) => {
+            match val.trim() {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Validates records against a list of typed fields, coercing mismatched types where safe.
+pub struct SchemaFilter {
+    fields: Vec<FieldSchema>,
+    coerce: bool,
+    on_invalid: OnInvalid,
+    coerced: Counter,
+    invalid: Counter,
+}
+
+impl SchemaFilter {
+    fn new(cfg: SchemaConfig, metrics: &Scope) -> SchemaFilter {
+        SchemaFilter {
+            fields: cfg.fields().clone(),
+            coerce: cfg.coerce(),
+            on_invalid: cfg.on_invalid(),
+            coerced: metrics.counter("zenlog_filter_coerced_total",
+                "Number of field values coerced or defaulted by the filter"),
+            invalid: metrics.counter("zenlog_filter_invalid_total",
+                "Number of records failed validation"),
+        }
+    }
+
+    /// Validates the given record, collecting replacements for field values and errors.
+    fn check(&self, record: &Record, changes: &mut Vec<(usize, Value)>, errors: &mut Vec<String>) {
+        for (id, schema) in self.fields.iter().enumerate() {
+            let field = schema.field();
+
+            match field.find(record) {
+                None | Some(&Value::Null) => {
+                    if let Some(default) = schema.default() {
+                        changes.push((id, default.clone()));
+                    } else if schema.required() {
+                        errors.push(format!("field '{}' is required", field));
+                    }
+                }
+                Some(value) => {
+                    if let Some(ty) = schema.ty() {
+                        if matches(ty, value) {
+                            continue;
+                        }
+
+                        let coerced = if self.coerce {
+                            coerce(ty, value)
+                        } else {
+                            None
+                        };
+
+                        match coerced {
+                            Some(value) => changes.push((id, value)),
+                            None => {
+                                errors.push(format!("field '{}' must be {}, got {}", field,
+                                    ty.as_str(), kind(value)));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Wraps the given invalid record into a dead letter.
+    fn deadletter(&self, record: Arc<Record>, errors: Vec<String>) -> Record {
+        let record = Arc::try_unwrap(record).unwrap_or_else(|record| (*record).clone());
+
+        let mut deadletter = BTreeMap::new();
+        deadletter.insert("record".to_owned(), record);
+        deadletter.insert("errors".to_owned(),
+            Value::Array(errors.into_iter().map(Value::String).collect()));
+
+        let mut result = BTreeMap::new();
+        result.insert("message".to_owned(),
+            Value::String("record failed schema validation".to_owned()));
+        result.insert("severity".to_owned(), Value::String(Severity::Error.as_str().to_owned()));
+        result.insert("timestamp".to_owned(), Value::I64(filter::timestamp()));
+        result.insert("deadletter".to_owned(), Value::Object(deadletter));
+
+        Value::Object(result)
+    }
+}
+
+impl Filter for SchemaFilter {
+    fn filter(&mut self, mut record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        let mut changes = Vec::new();
+        let mut errors = Vec::new();
+        self.check(&record, &mut changes, &mut errors);
+
+        if !errors.is_empty() {
+            self.invalid.inc();
+            debug!("invalid record {:?}: {}", record, errors.join(", "));
+
+            match self.on_invalid {
+                OnInvalid::Annotate => {}
+                OnInvalid::Drop => return,
+                OnInvalid::DeadLetter => {
+                    out.push(Arc::new(self.deadletter(record, errors)));
+                    return;
+                }
+            }
+        }
+
+        if !changes.is_empty() {
+            self.coerced.add(changes.len());
+
+            let record = Arc::make_mut(&mut record);
+            for (id, value) in changes {
+                self.fields[id].field().insert(record, value);
+            }
+        }
+
+        if !errors.is_empty() {
+            filter::tag(&mut record, INVALID_TAG);
+            filter::set(&mut record, ERRORS_FIELD,
+                Value::Array(errors.into_iter().map(Value::String).collect()), true);
+        }
+
+        out.push(record);
+    }
+}
+
+impl FilterFactory for SchemaFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "schema"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: SchemaConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(SchemaFilter::new(cfg, metrics)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{self, Value};
+
+    use filter::config::FieldType;
+
+    use super::coerce;
+
+    fn json(val: &str) -> Value {
+        serde_json::from_str(val).unwrap()
+    }
+
+    /// Checks coercion of JSON-encoded values, where `None` means the value is rejected.
+    fn check(ty: FieldType, cases: &[(&str, Option<&str>)]) {
+        for &(value, expected) in cases {
+            let actual = coerce(ty, &json(value));
+            let expected = expected.map(json);
+
+            assert!(actual == expected, "{:?} from {}: expected {:?}, got {:?}", ty, value,
+                expected, actual);
+        }
+    }
+
+    #[test]
+    fn coerce_integer() {
+        check(FieldType::Integer, &[
+            (r#""42""#, Some("42")),
+            (r#"" 42 ""#, Some("42")),
+            (r#""-42""#, Some("-42")),
+            (r#""18446744073709551615""#, Some("18446744073709551615")),
+            (r#""18446744073709551616""#, None),
+            (r#""4.0""#, None),
+            (r#""1e3""#, None),
+            (r#""NaN""#, None),
+            (r#""abc""#, None),
+            (r#""""#, None),
+            ("3.0", Some("3")),
+            ("-3.0", Some("-3")),
+            ("2.5", None),
+            ("9007199254740991.0", Some("9007199254740991")),
+            ("9007199254740992.0", None),
+            ("1e300", None),
+            ("true", None),
+            ("null", None),
+        ]);
+    }
+
+    #[test]
+    fn coerce_number() {
+        check(FieldType::Number, &[
+            (r#""1.5""#, Some("1.5")),
+            (r#""-2.5""#, Some("-2.5")),
+            (r#""1e3""#, Some("1000.0")),
+            (r#""42""#, Some("42")),
+            (r#""-42""#, Some("-42")),
+            (r#""9007199254740993""#, Some("9007199254740993")),
+            (r#""NaN""#, None),
+            (r#""inf""#, None),
+            (r#""-inf""#, None),
+            (r#""abc""#, None),
+            ("true", None),
+            ("[1]", None),
+        ]);
+    }
+
+    #[test]
+    fn coerce_string() {
+        check(FieldType::String, &[
+            ("42", Some(r#""42""#)),
+            ("-42", Some(r#""-42""#)),
+            ("1.5", Some(r#""1.5""#)),
+            ("true", Some(r#""true""#)),
+            ("null", None),
+            ("[1]", None),
+            ("{}", None),
+        ]);
+    }
+
+    #[test]
+    fn coerce_boolean() {
+        check(FieldType::Boolean, &[
+            (r#""true""#, Some("true")),
+            (r#"" false ""#, Some("false")),
+            (r#""TRUE""#, None),
+            (r#""1""#, None),
+            ("1", None),
+            ("null", None),
+        ]);
+    }
+
+    #[test]
+    fn never_coerce_into_containers() {
+        check(FieldType::Object, &[(r#""{}""#, None), ("[]", None)]);
+        check(FieldType::Array, &[(r#""[]""#, None), ("{}", None)]);
+    }
+}
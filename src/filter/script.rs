@@ -0,0 +1,103 @@
+use std::error::Error;
+use std::ffi::CString;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use {Config, Record};
+use config;
+use filter::{self, Filter, FilterFactory};
+use filter::config::{OnMismatch, ScriptConfig};
+use filter::lua::Lua;
+use metrics::{Counter, Scope};
+
+/// Tag appended to records, that the script failed to process, with `tag` error policy.
+const ERROR_TAG: &'static str = "_scriptfailure";
+
+/// Transforms records with a user-defined Lua function.
+///
+/// The function receives the record as a table and returns the resulting records: returning
+/// nothing or `nil` drops the record, returning several tables splits it, i.e.
+/// `return table.unpack(records)`.
+pub struct ScriptFilter {
+    lua: Lua,
+    function: CString,
+    timeout: Duration,
+    on_error: OnMismatch,
+    errors: Counter,
+}
+
+impl ScriptFilter {
+    fn new(cfg: ScriptConfig, metrics: &Scope) -> Result<ScriptFilter, Box<Error>> {
+        let function = try!(CString::new(cfg.function()));
+
+        let mut lua = Lua::new(cfg.memory());
+        try!(lua.load(cfg.script(), cfg.timeout())
+            .map_err(|err| format!("failed to load script: {}", err)));
+
+        if !lua.has_function(&function) {
+            return Err(format!("script must define '{}' function", cfg.function()).into());
+        }
+
+        let filter = ScriptFilter {
+            lua: lua,
+            function: function,
+            timeout: cfg.timeout(),
+            on_error: cfg.on_error(),
+            errors: metrics.counter("zenlog_filter_errors_total",
+                "Number of records the filter failed to process"),
+        };
+
+        Ok(filter)
+    }
+
+    fn call(&mut self, record: &Record) -> Result<Vec<Value>, String> {
+        let values = try!(self.lua.call(&self.function, record, self.timeout));
+
+        if values.iter().all(|val| val.is_object() || val.is_null()) {
+            Ok(values)
+        } else {
+            Err("function must return records only".to_owned())
+        }
+    }
+}
+
+impl Filter for ScriptFilter {
+    fn filter(&mut self, mut record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        match self.call(&record) {
+            Ok(values) => {
+                out.extend(values.into_iter().filter(Value::is_object).map(Arc::new));
+            }
+            Err(err) => {
+                debug!("script failed to process record: {}", err);
+                self.errors.inc();
+
+                match self.on_error {
+                    OnMismatch::Keep => {}
+                    OnMismatch::Drop => return,
+                    OnMismatch::Tag => filter::tag(&mut record, ERROR_TAG),
+                }
+
+                out.push(record);
+            }
+        }
+    }
+}
+
+impl FilterFactory for ScriptFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "script"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: ScriptConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(try!(ScriptFilter::new(cfg, metrics))))
+    }
+}
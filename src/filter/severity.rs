@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use {Config, Record};
+use config;
+use filter::{Filter, FilterFactory};
+use filter::config::SeverityConfig;
+use metrics::Scope;
+use severity::Severity;
+
+/// Drops records less severe than the configured threshold.
+///
+/// The threshold can be overridden for specific modules, using the `module` record field.
+/// Modules are hierarchical, separated with dots, so the override for `db` also applies to
+/// `db.pool`, unless there is a more specific one.
+///
+/// Records without any recognized severity field are always passed through.
+pub struct SeverityFilter {
+    threshold: Severity,
+    modules: BTreeMap<String, Severity>,
+}
+
+impl SeverityFilter {
+    fn new(cfg: SeverityConfig) -> SeverityFilter {
+        SeverityFilter {
+            threshold: cfg.threshold(),
+            modules: cfg.modules().clone(),
+        }
+    }
+
+    /// Returns the threshold for the given module, looking up its parents if required.
+    fn threshold(&self, module: Option<&str>) -> Severity {
+        let mut module = match module {
+            Some(module) => module,
+            None => return self.threshold,
+        };
+
+        loop {
+            if let Some(threshold) = self.modules.get(module) {
+                return *threshold;
+            }
+
+            match module.rfind('.') {
+                Some(pos) => module = &module[..pos],
+                None => return self.threshold,
+            }
+        }
+    }
+}
+
+impl Filter for SeverityFilter {
+    fn filter(&mut self, record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        let passed = match Severity::from_record(&record) {
+            Some(sev) => {
+                let module = record.find("module").and_then(|module| module.as_string());
+                sev <= self.threshold(module)
+            }
+            None => true,
+        };
+
+        if passed {
+            out.push(record);
+        }
+    }
+}
+
+impl FilterFactory for SeverityFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "severity"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, _metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: SeverityConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(SeverityFilter::new(cfg)))
+    }
+}
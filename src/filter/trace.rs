@@ -0,0 +1,413 @@
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use {Config, Record};
+use config;
+use filter::{self, Filter, FilterFactory};
+use filter::config::TraceConfig;
+use filter::pointer::Pointer;
+use metrics::{Counter, Scope};
+use severity::Severity;
+
+/// Returns the string representation of the given id, treating zero and empty ids as missing.
+fn key(value: &Value) -> Option<String> {
+    match *value {
+        Value::String(ref val) if !val.is_empty() => Some(val.clone()),
+        Value::I64(val) if val != 0 => Some(val.to_string()),
+        Value::U64(val) if val != 0 => Some(val.to_string()),
+        _ => None,
+    }
+}
+
+/// Records of a single span in order of arrival.
+struct Span {
+    id: Value,
+    parent_id: Value,
+    parent: Option<String>,
+    records: Vec<Value>,
+}
+
+/// Buffered trace with spans in order of their first record.
+struct Trace {
+    started: Instant,
+    id: Value,
+    count: usize,
+    spans: Vec<Span>,
+    /// Span positions by their ids. Records without span id form a span with an empty id.
+    positions: HashMap<String, usize>,
+}
+
+/// Moves the span with the given position into a tree node, along with all its descendants.
+///
+/// Spans already moved are skipped, which breaks parent cycles.
+fn build(pos: usize, spans: &mut Vec<Option<Span>>, children: &[Vec<usize>]) -> Option<Value> {
+    let span = match spans[pos].take() {
+        Some(span) => span,
+        None => return None,
+    };
+
+    let nested = children[pos].iter()
+        .filter_map(|&child| build(child, spans, children))
+        .collect();
+
+    let mut node = BTreeMap::new();
+    node.insert("span_id".to_owned(), span.id);
+    node.insert("parent_id".to_owned(), span.parent_id);
+    node.insert("records".to_owned(), Value::Array(span.records));
+    node.insert("children".to_owned(), Value::Array(nested));
+
+    Some(Value::Object(node))
+}
+
+impl Trace {
+    /// Reconstructs the span tree, returning the assembled trace record.
+    ///
+    /// Spans with missing or unknown parents become roots.
+    fn assemble(self, key: &str) -> Record {
+        let Trace { id, count, spans, positions, .. } = self;
+
+        let mut severity = None;
+        let mut timestamp = None;
+        for record in spans.iter().flat_map(|span| span.records.iter()) {
+            if let Some(sev) = Severity::from_record(record) {
+                severity = Some(severity.map_or(sev, |prev| cmp::min(prev, sev)));
+            }
+
+            if timestamp.is_none() {
+                timestamp = record.find("timestamp").cloned();
+            }
+        }
+
+        let mut children = vec![Vec::new(); spans.len()];
+        let mut roots = Vec::new();
+
+        for (pos, span) in spans.iter().enumerate() {
+            match span.parent.as_ref().and_then(|parent| positions.get(parent)) {
+                Some(&parent) if parent != pos => children[parent].push(pos),
+                _ => roots.push(pos),
+            }
+        }
+
+        let total = spans.len();
+        let mut spans: Vec<Option<Span>> = spans.into_iter().map(Some).collect();
+        let mut tree = Vec::new();
+
+        // Spans on parent cycles are unreachable from roots, so they are promoted to roots.
+        for pos in roots.into_iter().chain(0..total) {
+            if let Some(node) = build(pos, &mut spans, &children) {
+                tree.push(node);
+            }
+        }
+
+        let mut trace = BTreeMap::new();
+        trace.insert("id".to_owned(), id);
+        trace.insert("spans".to_owned(), Value::Array(tree));
+
+        let mut record = BTreeMap::new();
+        record.insert("message".to_owned(), Value::String(format!(
+            "trace {} with {} record(s) in {} span(s)", key, count, total)));
+        record.insert("severity".to_owned(),
+            Value::String(severity.unwrap_or(Severity::Info).as_str().to_owned()));
+        record.insert("timestamp".to_owned(),
+            timestamp.unwrap_or_else(|| Value::I64(filter::timestamp())));
+        record.insert("trace".to_owned(), Value::Object(trace));
+
+        Value::Object(record)
+    }
+}
+
+/// Buffers records per trace for a time window, emitting a single record with the reconstructed
+/// span tree at the end of the window.
+///
+/// Records without trace id are passed through unchanged. Pending traces are emitted on pipeline
+/// shutdown.
+pub struct TraceFilter {
+    trace_id: Pointer,
+    span_id: Pointer,
+    parent_id: Pointer,
+    window: Duration,
+    max_traces: usize,
+    max_records: usize,
+    traces: HashMap<String, Trace>,
+    overflows: Counter,
+}
+
+impl TraceFilter {
+    fn new(cfg: TraceConfig, metrics: &Scope) -> Result<TraceFilter, Box<Error>> {
+        if cfg.max_records() == 0 {
+            return Err("max_records must be positive".into());
+        }
+
+        let filter = TraceFilter {
+            trace_id: cfg.trace_id(),
+            span_id: cfg.span_id(),
+            parent_id: cfg.parent_id(),
+            window: cfg.window(),
+            max_traces: cfg.max_traces(),
+            max_records: cfg.max_records(),
+            traces: HashMap::new(),
+            overflows: metrics.counter("zenlog_filter_overflows_total",
+                "Number of records not buffered because of the traces limit"),
+        };
+
+        Ok(filter)
+    }
+}
+
+impl Filter for TraceFilter {
+    fn filter(&mut self, record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        let id = match self.trace_id.find(&record) {
+            Some(id) => id.clone(),
+            None => {
+                out.push(record);
+                return;
+            }
+        };
+
+        let key = match key(&id) {
+            Some(key) => key,
+            None => {
+                out.push(record);
+                return;
+            }
+        };
+
+        // Protect from unbounded memory growth on trace floods.
+        if !self.traces.contains_key(&key) && self.traces.len() >= self.max_traces {
+            self.overflows.inc();
+            out.push(record);
+            return;
+        }
+
+        let span_id = self.span_id.find(&record).cloned().unwrap_or(Value::Null);
+        let parent_id = self.parent_id.find(&record).cloned().unwrap_or(Value::Null);
+        let span = key(&span_id).unwrap_or_else(String::new);
+        let parent = key(&parent_id);
+
+        let full = {
+            let trace = self.traces.entry(key.clone()).or_insert_with(|| {
+                Trace {
+                    started: Instant::now(),
+                    id: id,
+                    count: 0,
+                    spans: Vec::new(),
+                    positions: HashMap::new(),
+                }
+            });
+
+            let pos = match trace.positions.get(&span) {
+                Some(&pos) => pos,
+                None => {
+                    trace.spans.push(Span {
+                        id: span_id,
+                        parent_id: parent_id,
+                        parent: parent,
+                        records: Vec::new(),
+                    });
+                    trace.spans.len() - 1
+                }
+            };
+            trace.positions.insert(span, pos);
+
+            let record = Arc::try_unwrap(record).unwrap_or_else(|record| (*record).clone());
+            trace.spans[pos].records.push(record);
+            trace.count += 1;
+
+            trace.count >= self.max_records
+        };
+
+        if full {
+            if let Some(trace) = self.traces.remove(&key) {
+                out.push(Arc::new(trace.assemble(&key)));
+            }
+        }
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Arc<Record>>) {
+        let window = self.window;
+        let expired: Vec<String> = self.traces.iter()
+            .filter(|&(_, trace)| now.duration_since(trace.started) >= window)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in expired {
+            if let Some(trace) = self.traces.remove(&key) {
+                out.push(Arc::new(trace.assemble(&key)));
+            }
+        }
+    }
+
+    fn flush(&mut self, out: &mut Vec<Arc<Record>>) {
+        for (key, trace) in self.traces.drain() {
+            out.push(Arc::new(trace.assemble(&key)));
+        }
+    }
+}
+
+impl FilterFactory for TraceFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "trace"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: TraceConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(try!(TraceFilter::new(cfg, metrics))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::{self, Value};
+
+    use filter::Filter;
+    use metrics::Metrics;
+
+    use super::TraceFilter;
+
+    fn filter(cfg: &str) -> TraceFilter {
+        let cfg = serde_json::from_str(cfg).unwrap();
+        TraceFilter::new(cfg, &Metrics::new().scope(&[])).unwrap()
+    }
+
+    /// Feeds the given records into the filter, returning everything it emitted, including
+    /// pending traces.
+    fn run(filter: &mut TraceFilter, records: &[&str]) -> Vec<Value> {
+        let mut out = Vec::new();
+
+        for record in records {
+            filter.filter(Arc::new(serde_json::from_str(record).unwrap()), &mut out);
+        }
+
+        filter.flush(&mut out);
+
+        out.into_iter().map(|record| (*record).clone()).collect()
+    }
+
+    /// Renders the span tree compactly, i.e. "a(b c)", missing span ids are rendered as "-".
+    fn shape(nodes: &Value) -> String {
+        let nodes: Vec<String> = nodes.as_array().unwrap().iter()
+            .map(|node| {
+                let id = match *node.find("span_id").unwrap() {
+                    Value::String(ref id) => id.clone(),
+                    _ => "-".to_owned(),
+                };
+
+                match shape(node.find("children").unwrap()) {
+                    ref children if children.is_empty() => id,
+                    children => format!("{}({})", id, children),
+                }
+            })
+            .collect();
+
+        nodes.join(" ")
+    }
+
+    fn spans(record: &Value) -> &Value {
+        record.find("trace").and_then(|trace| trace.find("spans")).unwrap()
+    }
+
+    #[test]
+    fn build_tree() {
+        let mut filter = filter("{}");
+        let out = run(&mut filter, &[
+            r#"{"trace_id": "t", "span_id": "a"}"#,
+            r#"{"trace_id": "t", "span_id": "b", "parent_id": "a"}"#,
+            r#"{"trace_id": "t", "span_id": "c", "parent_id": "b"}"#,
+            r#"{"trace_id": "t", "span_id": "d", "parent_id": "a"}"#,
+            r#"{"trace_id": "t", "span_id": "b", "parent_id": "a"}"#,
+        ]);
+
+        assert_eq!(1, out.len());
+        assert_eq!("a(b(c) d)", shape(spans(&out[0])));
+        assert_eq!(Some("trace t with 5 record(s) in 4 span(s)"),
+            out[0].find("message").and_then(|val| val.as_string()));
+    }
+
+    #[test]
+    fn unknown_parents_become_roots() {
+        let mut filter = filter("{}");
+        let out = run(&mut filter, &[
+            r#"{"trace_id": "t", "span_id": "a"}"#,
+            r#"{"trace_id": "t", "span_id": "b", "parent_id": "x"}"#,
+            r#"{"trace_id": "t", "span_id": "c", "parent_id": "b"}"#,
+        ]);
+
+        assert_eq!("a b(c)", shape(spans(&out[0])));
+    }
+
+    #[test]
+    fn promote_parent_cycles() {
+        let mut filter = filter("{}");
+        let out = run(&mut filter, &[
+            r#"{"trace_id": "t", "span_id": "a", "parent_id": "b"}"#,
+            r#"{"trace_id": "t", "span_id": "b", "parent_id": "a"}"#,
+            r#"{"trace_id": "t", "span_id": "r"}"#,
+            r#"{"trace_id": "t", "span_id": "s", "parent_id": "s"}"#,
+        ]);
+
+        assert_eq!("r s a(b)", shape(spans(&out[0])));
+    }
+
+    #[test]
+    fn group_records_without_span_id() {
+        let mut filter = filter("{}");
+        let out = run(&mut filter, &[
+            r#"{"trace_id": "t", "message": "first"}"#,
+            r#"{"trace_id": "t", "span_id": "", "message": "second"}"#,
+            r#"{"trace_id": "t", "span_id": "a"}"#,
+        ]);
+
+        assert_eq!("- a", shape(spans(&out[0])));
+
+        let records = spans(&out[0]).as_array().unwrap()[0].find("records").unwrap();
+        assert_eq!(2, records.as_array().unwrap().len());
+    }
+
+    #[test]
+    fn pass_records_without_trace_id() {
+        let mut filter = filter("{}");
+        let out = run(&mut filter, &[
+            r#"{"message": "plain"}"#,
+            r#"{"trace_id": 0, "message": "zero"}"#,
+        ]);
+
+        assert_eq!(vec![
+            serde_json::from_str::<Value>(r#"{"message": "plain"}"#).unwrap(),
+            serde_json::from_str::<Value>(r#"{"trace_id": 0, "message": "zero"}"#).unwrap(),
+        ], out);
+    }
+
+    #[test]
+    fn emit_full_traces_early() {
+        let mut filter = filter(r#"{"max_records": 2}"#);
+        let mut out = Vec::new();
+
+        for _ in 0..3 {
+            let record = serde_json::from_str(r#"{"trace_id": "t", "span_id": "a"}"#).unwrap();
+            filter.filter(Arc::new(record), &mut out);
+        }
+
+        assert_eq!(1, out.len());
+        assert_eq!(Some("trace t with 2 record(s) in 1 span(s)"),
+            out[0].find("message").and_then(|val| val.as_string()));
+
+        filter.flush(&mut out);
+
+        assert_eq!(2, out.len());
+        assert_eq!(Some("trace t with 1 record(s) in 1 span(s)"),
+            out[1].find("message").and_then(|val| val.as_string()));
+    }
+}
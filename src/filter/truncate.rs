@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use {Config, Record};
+use config::{self, LimitsConfig};
+use filter::{Filter, FilterFactory};
+use limits::Limits;
+use metrics::{Counter, Scope};
+
+/// Truncates records exceeding the size limits, i.e. produced by preceding filters.
+///
+/// Accepts the same options as source `limits`.
+pub struct TruncateFilter {
+    limits: Limits,
+    truncated: Counter,
+}
+
+impl TruncateFilter {
+    fn new(cfg: LimitsConfig, metrics: &Scope) -> Result<TruncateFilter, Box<Error>> {
+        let limits = Limits::new(&cfg);
+
+        if limits.is_empty() {
+            return Err("at least one limit is required".into());
+        }
+
+        let filter = TruncateFilter {
+            limits: limits,
+            truncated: metrics.counter("zenlog_filter_truncated_total",
+                "Number of records truncated by the filter"),
+        };
+
+        Ok(filter)
+    }
+}
+
+impl Filter for TruncateFilter {
+    fn filter(&mut self, mut record: Arc<Record>, out: &mut Vec<Arc<Record>>) {
+        if self.limits.exceeds(&record) && self.limits.apply(Arc::make_mut(&mut record)) {
+            self.truncated.inc();
+        }
+
+        out.push(record);
+    }
+}
+
+impl FilterFactory for TruncateFilter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "truncate"
+    }
+
+    fn from(cfg: &Config, _pipeline: &str, _base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Self::Error>
+    {
+        let cfg: LimitsConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(try!(TruncateFilter::new(cfg, metrics))))
+    }
+}
@@ -0,0 +1,121 @@
+//! Liveness tracking for threads spawned by pipelines and their components.
+//!
+//! Each component that owns a thread registers a probe, which lives as long as the component
+//! itself, and moves a guard into the thread. The guard marks the thread as dead when dropped,
+//! unless the thread has explicitly reported its clean completion, for example on EOF.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const RUNNING: usize = 0;
+const FINISHED: usize = 1;
+const DEAD: usize = 2;
+
+struct Entry {
+    name: String,
+    state: Arc<AtomicUsize>,
+}
+
+#[derive(Default)]
+struct Inner {
+    counter: usize,
+    entries: BTreeMap<usize, Entry>,
+}
+
+/// Shared liveness registry.
+#[derive(Clone, Default)]
+pub struct Health {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Health {
+    pub fn new() -> Health {
+        Health::default()
+    }
+
+    /// Registers a new thread with the given name.
+    pub fn register(&self, name: String) -> Probe {
+        let state = Arc::new(AtomicUsize::new(RUNNING));
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.counter += 1;
+
+        let id = inner.counter;
+        inner.entries.insert(id, Entry { name: name, state: state.clone() });
+
+        Probe {
+            id: id,
+            state: state,
+            health: self.clone(),
+        }
+    }
+
+    /// Returns whether all registered threads are either running or finished cleanly, together
+    /// with a human-readable report, one line per thread.
+    pub fn report(&self) -> (bool, String) {
+        let inner = self.inner.lock().unwrap();
+
+        let mut healthy = true;
+        let mut report = String::new();
+
+        for entry in inner.entries.values() {
+            let state = match entry.state.load(Ordering::SeqCst) {
+                RUNNING => "running",
+                FINISHED => "finished",
+                _ => {
+                    healthy = false;
+                    "dead"
+                }
+            };
+
+            writeln!(report, "{} {}", entry.name, state).unwrap();
+        }
+
+        (healthy, report)
+    }
+}
+
+/// Registration of a thread in the liveness registry.
+///
+/// The thread is unregistered when the probe is dropped.
+pub struct Probe {
+    id: usize,
+    state: Arc<AtomicUsize>,
+    health: Health,
+}
+
+impl Probe {
+    /// Returns a guard that should be moved into the monitored thread.
+    pub fn guard(&self) -> Guard {
+        Guard {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl Drop for Probe {
+    fn drop(&mut self) {
+        self.health.inner.lock().unwrap().entries.remove(&self.id);
+    }
+}
+
+/// Marks the monitored thread as dead on drop, unless it has been finished cleanly.
+pub struct Guard {
+    state: Arc<AtomicUsize>,
+}
+
+impl Guard {
+    /// Reports clean completion of the monitored thread.
+    pub fn finish(self) {
+        self.state.store(FINISHED, Ordering::SeqCst);
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        // Finished threads must stay finished, so the result is deliberately ignored.
+        let _ = self.state.compare_exchange(RUNNING, DEAD, Ordering::SeqCst, Ordering::SeqCst);
+    }
+}
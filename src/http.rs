@@ -0,0 +1,146 @@
+//! Embedded HTTP listener, exposing internal metrics and health status.
+//!
+//! Only two resources are served:
+//!  - `/metrics` - all internal metrics in the Prometheus text exposition format.
+//!  - `/health` - liveness of each pipeline and source thread. Responds with 503 if any of them
+//!    is dead.
+//!
+//! Requests are handled sequentially in a single thread, which is more than enough for scraping.
+
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use metrics::Metrics;
+
+/// Maximum request head size we are going to read.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+pub struct Server {
+    endpoint: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn new(endpoint: &SocketAddr, metrics: Metrics) -> Result<Server, Box<Error>> {
+        let listener = try!(TcpListener::bind(endpoint));
+        let endpoint = try!(listener.local_addr());
+        info!("exposed HTTP metrics endpoint on {}", endpoint);
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stopped = stopped.clone();
+            thread::spawn(move || Server::run(listener, metrics, stopped))
+        };
+
+        let server = Server {
+            endpoint: endpoint,
+            stopped: stopped,
+            thread: Some(thread),
+        };
+
+        Ok(server)
+    }
+
+    fn run(listener: TcpListener, metrics: Metrics, stopped: Arc<AtomicBool>) {
+        for stream in listener.incoming() {
+            if stopped.load(Ordering::SeqCst) {
+                break;
+            }
+
+            match stream {
+                Ok(stream) => {
+                    if let Err(err) = Server::handle(stream, &metrics) {
+                        warn!("failed to handle HTTP request: {}", err);
+                    }
+                }
+                Err(err) => {
+                    warn!("failed to accept HTTP connection: {}", err);
+                }
+            }
+        }
+
+        debug!("stopped HTTP metrics endpoint");
+    }
+
+    fn handle(mut stream: TcpStream, metrics: &Metrics) -> Result<(), io::Error> {
+        try!(stream.set_read_timeout(Some(Duration::from_secs(5))));
+        try!(stream.set_write_timeout(Some(Duration::from_secs(5))));
+
+        let head = try!(read_head(&mut stream));
+
+        let mut parts = head.lines().next().unwrap_or("").split_whitespace();
+        let method = parts.next().unwrap_or("");
+        let path = parts.next().unwrap_or("");
+        // Ignore query string, if any.
+        let path = path.split('?').next().unwrap_or("");
+
+        debug!("HTTP request: {} {}", method, path);
+
+        if method != "GET" {
+            return respond(&mut stream, "405 Method Not Allowed", "text/plain", "");
+        }
+
+        match path {
+            "/metrics" => {
+                respond(&mut stream, "200 OK", "text/plain; version=0.0.4", &metrics.render())
+            }
+            "/health" => {
+                let (healthy, report) = metrics.health().report();
+                let status = if healthy { "200 OK" } else { "503 Service Unavailable" };
+
+                respond(&mut stream, status, "text/plain", &report)
+            }
+            _ => respond(&mut stream, "404 Not Found", "text/plain", ""),
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+
+        // Wake up the listener, blocked on accept.
+        if let Err(err) = TcpStream::connect(&self.endpoint) {
+            error!("failed to wake up HTTP listener: {}", err);
+            return;
+        }
+
+        if let Err(err) = self.thread.take().expect("thread must exist").join() {
+            error!("failed to gracefully stop HTTP listener: {:?}", err);
+        }
+    }
+}
+
+/// Reads the request head, i.e. everything before the empty line.
+fn read_head(stream: &mut TcpStream) -> Result<String, io::Error> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 1024];
+
+    while !buf.ends_with(b"\r\n\r\n") && !buf.ends_with(b"\n\n") {
+        let nread = try!(stream.read(&mut chunk));
+        if nread == 0 {
+            break;
+        }
+
+        buf.extend_from_slice(&chunk[..nread]);
+
+        if buf.len() > MAX_REQUEST_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "request is too large"));
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+fn respond(stream: &mut TcpStream, status: &str, ty: &str, body: &str) -> Result<(), io::Error> {
+    try!(write!(stream, "HTTP/1.0 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+        Connection: close\r\n\r\n", status, ty, body.len()));
+    try!(stream.write_all(body.as_bytes()));
+    stream.flush()
+}
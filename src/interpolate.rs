@@ -0,0 +1,230 @@
+//! Config values interpolation.
+//!
+//! Each string value in the config is processed before the config is decoded:
+//!  - `${VAR}` is replaced with the value of `VAR` environment variable, which must be defined.
+//!  - `${VAR:-default}` is replaced with the value of `VAR`, or with `default` if the variable is
+//!    either undefined or empty.
+//!  - `$$` is replaced with a single `$`.
+//!  - the whole value of form `@file:/path` is replaced with the content of the given file without
+//!    trailing newline, which is useful for referencing secrets.
+//!
+//! Any other `$` is kept as is, so regex anchors like `^foo$` need no escaping.
+//!
+//! # Note
+//!
+//! Interpolation applies to all strings, including regular expressions, grok patterns and
+//! inline scripts. A literal `${` or `$$` there must be written as `$${` or `$$$$` respectively.
+//! File contents are never interpolated, so scripts referenced via `@file:` are left untouched.
+
+use std::env::{self, VarError};
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+
+use serde_json::Value;
+
+const FILE_PREFIX: &'static str = "@file:";
+
+/// Resolves all variables and file references in the given value recursively.
+pub fn interpolate(value: &mut Value) -> Result<(), Box<Error>> {
+    walk(value, "")
+}
+
+fn walk(value: &mut Value, path: &str) -> Result<(), Box<Error>> {
+    match *value {
+        Value::String(ref mut val) => {
+            *val = try!(resolve(val).map_err(|err| format!("failed to interpolate '{}': {}",
+                path, err)));
+        }
+        Value::Array(ref mut vec) => {
+            for (id, val) in vec.iter_mut().enumerate() {
+                try!(walk(val, &format!("{}[{}]", path, id)));
+            }
+        }
+        Value::Object(ref mut map) => {
+            for (key, val) in map.iter_mut() {
+                if path.is_empty() {
+                    try!(walk(val, key));
+                } else {
+                    try!(walk(val, &format!("{}.{}", path, key)));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn resolve(val: &str) -> Result<String, String> {
+    if val.starts_with(FILE_PREFIX) {
+        read(&val[FILE_PREFIX.len()..])
+    } else {
+        expand(val)
+    }
+}
+
+fn read(path: &str) -> Result<String, String> {
+    let mut content = String::new();
+
+    try!(File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut content))
+        .map_err(|err| format!("failed to read '{}': {}", path, err)));
+
+    while content.ends_with('\n') || content.ends_with('\r') {
+        content.pop();
+    }
+
+    Ok(content)
+}
+
+fn expand(val: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(val.len());
+    let mut rest = val;
+
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        if rest.starts_with("$$") {
+            result.push('$');
+            rest = &rest[2..];
+        } else if rest.starts_with("${") {
+            let end = try!(rest.find('}').ok_or("unterminated variable reference"));
+            result.push_str(&try!(substitute(&rest[2..end])));
+            rest = &rest[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+/// Substitutes a single variable reference, i.e. `VAR` or `VAR:-default`.
+fn substitute(expr: &str) -> Result<String, String> {
+    let (name, default) = match expr.find(":-") {
+        Some(pos) => (&expr[..pos], Some(&expr[pos + 2..])),
+        None => (expr, None),
+    };
+
+    if name.is_empty() || !name.chars().all(|ch| ch.is_alphanumeric() || ch == '_') {
+        return Err(format!("invalid variable name '{}'", name));
+    }
+
+    match (env::var(name), default) {
+        (Ok(ref val), Some(default)) if val.is_empty() => Ok(default.to_owned()),
+        (Ok(val), _) => Ok(val),
+        (Err(VarError::NotPresent), Some(default)) => Ok(default.to_owned()),
+        (Err(VarError::NotPresent), None) => {
+            Err(format!("undefined environment variable '{}'", name))
+        }
+        (Err(VarError::NotUnicode(..)), _) => {
+            Err(format!("environment variable '{}' is not valid unicode", name))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    use serde_json::{self, Value};
+
+    use super::{expand, interpolate, resolve};
+
+    #[test]
+    fn expand_plain() {
+        assert_eq!("plain text", expand("plain text").unwrap());
+    }
+
+    #[test]
+    fn expand_escaped_dollar() {
+        assert_eq!("$", expand("$$").unwrap());
+        assert_eq!("$${", expand("$$$${").unwrap());
+        assert_eq!("${HOME}", expand("$${HOME}").unwrap());
+    }
+
+    #[test]
+    fn expand_keeps_lone_dollar() {
+        assert_eq!("^foo$", expand("^foo$").unwrap());
+        assert_eq!("$1 and $x", expand("$1 and $x").unwrap());
+    }
+
+    #[test]
+    fn expand_variable() {
+        env::set_var("ZENLOG_TEST_EXPAND", "value");
+        assert_eq!("a-value-b", expand("a-${ZENLOG_TEST_EXPAND}-b").unwrap());
+    }
+
+    #[test]
+    fn expand_default() {
+        env::remove_var("ZENLOG_TEST_UNDEFINED");
+        assert_eq!("fallback", expand("${ZENLOG_TEST_UNDEFINED:-fallback}").unwrap());
+        assert_eq!("", expand("${ZENLOG_TEST_UNDEFINED:-}").unwrap());
+
+        env::set_var("ZENLOG_TEST_EMPTY", "");
+        assert_eq!("fallback", expand("${ZENLOG_TEST_EMPTY:-fallback}").unwrap());
+
+        env::set_var("ZENLOG_TEST_DEFINED", "value");
+        assert_eq!("value", expand("${ZENLOG_TEST_DEFINED:-fallback}").unwrap());
+    }
+
+    #[test]
+    fn expand_undefined() {
+        env::remove_var("ZENLOG_TEST_MISSING");
+        assert!(expand("${ZENLOG_TEST_MISSING}").is_err());
+    }
+
+    #[test]
+    fn expand_malformed() {
+        assert!(expand("${UNTERMINATED").is_err());
+        assert!(expand("${}").is_err());
+        assert!(expand("${INVALID-NAME}").is_err());
+    }
+
+    #[test]
+    fn resolve_file() {
+        let path = env::temp_dir().join("zenlog-interpolate-test");
+        File::create(&path).unwrap().write_all(b"secret $${not-expanded}\n").unwrap();
+
+        let result = resolve(&format!("@file:{}", path.display()));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!("secret $${not-expanded}", result.unwrap());
+    }
+
+    #[test]
+    fn resolve_missing_file() {
+        assert!(resolve("@file:/nonexistent/zenlog").is_err());
+    }
+
+    #[test]
+    fn interpolate_nested() {
+        env::set_var("ZENLOG_TEST_NESTED", "value");
+
+        let mut value: Value = serde_json::from_str(
+            r#"{"a": ["${ZENLOG_TEST_NESTED}", 42], "b": {"c": "$$"}}"#).unwrap();
+        interpolate(&mut value).unwrap();
+
+        let expected: Value = serde_json::from_str(r#"{"a": ["value", 42], "b": {"c": "$"}}"#)
+            .unwrap();
+        assert_eq!(expected, value);
+    }
+
+    #[test]
+    fn interpolate_error_path() {
+        env::remove_var("ZENLOG_TEST_ABSENT");
+
+        let mut value: Value = serde_json::from_str(r#"{"a": [{"b": "${ZENLOG_TEST_ABSENT}"}]}"#)
+            .unwrap();
+        let err = interpolate(&mut value).unwrap_err();
+
+        assert!(err.to_string().contains("a[0].b"));
+    }
+}
@@ -1,30 +1,53 @@
 #[macro_use]
 extern crate log;
+#[macro_use]
+extern crate chan;
 extern crate libc;
+extern crate lua52_sys;
 extern crate chrono;
+extern crate csv;
+extern crate crypto;
 extern crate mio;
+extern crate regex;
+extern crate serde;
 extern crate serde_json;
 extern crate termion;
 
 use std::collections::HashMap;
 use std::error::Error;
+use std::path::Path;
 use std::thread::{self, JoinHandle};
-use std::sync::{mpsc, Arc};
-use std::sync::mpsc::Sender;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::sync::mpsc::{RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
 
 use serde_json::Value;
 
 mod config;
+mod filter;
+mod interpolate;
+mod limits;
 mod output;
 mod source;
 mod record;
 
+pub mod admin;
+pub mod expr;
+pub mod health;
+pub mod http;
 pub mod logging;
-
+pub mod metrics;
+pub mod severity;
+
+use admin::Shutdown;
+use expr::Predicate;
+use filter::{Filter, FilterFactory};
+use health::Probe;
+use metrics::{Counter, Gauge, Histogram, Metrics, Scope};
 use output::{Output, OutputFactory};
 use source::{Source, SourceFactory};
 
-use config::PipeConfig;
+use config::{OutputRoute, PipeConfig};
 pub use config::RuntimeConfig;
 
 pub type Record = Value;
@@ -33,38 +56,79 @@ pub type Config = Value;
 // TODO: Add `info!` log when a pipe/input stopped.
 // TODO: Record as a trait.
 
+/// Interval between filter ticks in seconds.
+const TICK_INTERVAL: u64 = 1;
+
 enum Control {
     Hup,
     Shutdown,
 }
 
-type FnSourceFactory = Fn(&Config, Sender<Arc<Record>>) -> Result<Box<Source>, Box<Error>>;
-type FnOutputFactory = Fn(&Config) -> Result<Box<Output>, Box<Error>>;
+type FnSourceFactory = Fn(&Config, Sender<Arc<Record>>, &Scope, &Shutdown) ->
+    Result<Box<Source>, Box<Error>>;
+type FnFilterFactory = Fn(&Config, &str, &Path, &Scope) -> Result<Box<Filter>, Box<Error>>;
+type FnOutputFactory = Fn(&Config, &Scope) -> Result<Box<Output>, Box<Error>>;
 
-#[derive(Default)]
 pub struct Registry {
     sources: HashMap<&'static str, Box<FnSourceFactory>>,
+    filters: HashMap<&'static str, Box<FnFilterFactory>>,
     outputs: HashMap<&'static str, Box<FnOutputFactory>>,
+    metrics: Metrics,
+    shutdown: Shutdown,
 }
 
 impl Registry {
-    pub fn new() -> Registry {
+    /// Constructs the registry with all known components.
+    ///
+    /// Sources may request the graceful shutdown by sending into the given unbounded channel,
+    /// which should be served by the main loop.
+    pub fn new(shutdown: chan::Sender<()>) -> Registry {
         info!("registering components");
 
-        let mut registry = Registry::default();
+        let mut registry = Registry {
+            sources: HashMap::new(),
+            filters: HashMap::new(),
+            outputs: HashMap::new(),
+            metrics: Metrics::default(),
+            shutdown: Shutdown::new(shutdown),
+        };
         registry.add_source::<source::StdinSource>();
         registry.add_source::<source::UdpSource>();
 
+        registry.add_filter::<filter::SeverityFilter>();
+        registry.add_filter::<filter::HostnameFilter>();
+        registry.add_filter::<filter::FieldsFilter>();
+        registry.add_filter::<filter::RouteFilter>();
+        registry.add_filter::<filter::GrokFilter>();
+        registry.add_filter::<filter::DecodeFilter>();
+        registry.add_filter::<filter::RateLimitFilter>();
+        registry.add_filter::<filter::SampleFilter>();
+        registry.add_filter::<filter::DedupFilter>();
+        registry.add_filter::<filter::MultilineFilter>();
+        registry.add_filter::<filter::RedactFilter>();
+        registry.add_filter::<filter::ScriptFilter>();
+        registry.add_filter::<filter::LookupFilter>();
+        registry.add_filter::<filter::SchemaFilter>();
+        registry.add_filter::<filter::TruncateFilter>();
+        registry.add_filter::<filter::AggregateFilter>();
+        registry.add_filter::<filter::TraceFilter>();
+
         registry.add_output::<output::Dev>();
+        registry.add_output::<output::Exporter>();
 
         registry
     }
 
+    /// Returns the metrics registry shared between all components constructed by this registry.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
     /// Registers a source with the factory.
     fn add_source<T: SourceFactory + 'static>(&mut self) {
         self.sources.insert(T::ty(),
-            Box::new(|cfg, tx| {
-                T::run(cfg, tx)
+            Box::new(|cfg, tx, metrics, shutdown| {
+                T::run(cfg, tx, metrics, shutdown)
                     .map_err(Into::into)
             })
         );
@@ -72,10 +136,21 @@ impl Registry {
         debug!("registered {} component in 'source' category", T::ty());
     }
 
+    fn add_filter<T: FilterFactory + 'static>(&mut self) {
+        self.filters.insert(T::ty(),
+            Box::new(|cfg, pipeline, base, metrics| {
+                T::from(cfg, pipeline, base, metrics)
+                    .map_err(Into::into)
+            })
+        );
+
+        debug!("registered {} component in 'filter' category", T::ty());
+    }
+
     fn add_output<T: OutputFactory + 'static>(&mut self) {
         self.outputs.insert(T::ty(),
-            Box::new(|cfg| {
-                T::from(cfg)
+            Box::new(|cfg, metrics| {
+                T::from(cfg, metrics)
                     .map_err(Into::into)
             })
         );
@@ -83,20 +158,43 @@ impl Registry {
         debug!("registered {} component in 'output' category", T::ty());
     }
 
-    fn source(&self, cfg: &Config, tx: Sender<Arc<Record>>) -> Result<Box<Source>, Box<Error>> {
+    fn source(&self, cfg: &Config, tx: Sender<Arc<Record>>, metrics: &Scope) ->
+        Result<Box<Source>, Box<Error>>
+    {
         Registry::ty(cfg)
             .map_err(Into::into)
             .and_then(|ty| self.sources.get(ty)
                 .ok_or("source not found".into()))
-            .and_then(|factory| factory(cfg, tx))
+            .and_then(|factory| factory(cfg, tx, metrics, &self.shutdown))
+            .map_err(|err| Registry::context("source", cfg, err))
+    }
+
+    fn filter(&self, cfg: &Config, pipeline: &str, base: &Path, metrics: &Scope) ->
+        Result<Box<Filter>, Box<Error>>
+    {
+        Registry::ty(cfg)
+            .map_err(Into::into)
+            .and_then(|ty| self.filters.get(ty)
+                .ok_or("filter not found".into()))
+            .and_then(|factory| factory(cfg, pipeline, base, metrics))
+            .map_err(|err| Registry::context("filter", cfg, err))
     }
 
-    fn output(&self, cfg: &Config) -> Result<Box<Output>, Box<Error>> {
+    fn output(&self, cfg: &Config, metrics: &Scope) -> Result<Box<Output>, Box<Error>> {
         Registry::ty(cfg)
             .map_err(Into::into)
             .and_then(|ty| self.outputs.get(ty)
                 .ok_or("output not found".into()))
-            .and_then(|factory| factory(cfg))
+            .and_then(|factory| factory(cfg, metrics))
+            .map_err(|err| Registry::context("output", cfg, err))
+    }
+
+    /// Attaches component category and type to the given construction error.
+    fn context(category: &str, cfg: &Config, err: Box<Error>) -> Box<Error> {
+        match Registry::ty(cfg) {
+            Ok(ty) => format!("failed to construct '{}' {}: {}", ty, category, err).into(),
+            Err(..) => err,
+        }
     }
 
     fn ty(cfg: &Config) -> Result<&str, &str> {
@@ -107,6 +205,214 @@ impl Registry {
     }
 }
 
+/// Output attached to a pipeline together with its routing options and metrics.
+struct Sink {
+    name: String,
+    when: Option<Predicate>,
+    output: Box<Output>,
+    handled: Counter,
+    failed: Counter,
+}
+
+impl Sink {
+    fn handle(&mut self, record: &Arc<Record>) {
+        if let Some(ref when) = self.when {
+            if !when.matches(record) {
+                return;
+            }
+        }
+
+        match self.output.handle(record) {
+            Ok(()) => self.handled.inc(),
+            Err(err) => {
+                self.failed.inc();
+                error!("failed to handle {:?}: {}", record, err);
+            }
+        }
+    }
+}
+
+/// Pipeline routing rule with output names resolved into indices.
+struct Route {
+    when: Option<Predicate>,
+    outputs: Vec<usize>,
+}
+
+impl Route {
+    /// Returns outputs of the first rule, that matches the given record.
+    fn select<'a>(routes: &'a [Route], record: &Record) -> Option<&'a [usize]> {
+        routes.iter()
+            .find(|route| route.when.as_ref().map_or(true, |when| when.matches(record)))
+            .map(|route| &route.outputs[..])
+    }
+}
+
+/// Passes the given records through the filter chain, returning the resulting records.
+fn apply(filters: &mut [Box<Filter>], records: Vec<Arc<Record>>) -> Vec<Arc<Record>> {
+    let mut records = records;
+
+    for filter in filters {
+        let mut out = Vec::with_capacity(records.len());
+
+        for record in records {
+            filter.filter(record, &mut out);
+        }
+
+        if out.is_empty() {
+            return out;
+        }
+
+        records = out;
+    }
+
+    records
+}
+
+/// Calls the given function for each filter, passing records it emits through the rest of the
+/// chain.
+fn emit<F>(filters: &mut [Box<Filter>], mut f: F) -> Vec<Arc<Record>>
+    where F: FnMut(&mut Box<Filter>, &mut Vec<Arc<Record>>)
+{
+    let mut records = Vec::new();
+
+    for id in 0..filters.len() {
+        let mut out = Vec::new();
+        f(&mut filters[id], &mut out);
+
+        if !out.is_empty() {
+            records.extend(apply(&mut filters[id + 1..], out));
+        }
+    }
+
+    records
+}
+
+/// Pipeline outputs together with the routing table.
+struct Sinks {
+    outputs: Vec<Sink>,
+    routes: Vec<Route>,
+    unrouted: Counter,
+}
+
+impl Sinks {
+    /// Sends the given record to outputs, selected by the routing table.
+    fn dispatch(&mut self, record: &Arc<Record>) {
+        if self.routes.is_empty() {
+            for sink in &mut self.outputs {
+                sink.handle(record);
+            }
+
+            return;
+        }
+
+        match Route::select(&self.routes, record) {
+            Some(ids) => {
+                for &id in ids {
+                    self.outputs[id].handle(record);
+                }
+            }
+            None => {
+                trace!("drop {:?}: no matching route", record);
+                self.unrouted.inc();
+            }
+        }
+    }
+}
+
+/// Pipeline-wide metrics.
+struct PipeMetrics {
+    received: Counter,
+    dropped: Counter,
+    queue: Gauge,
+    latency: Histogram,
+    /// Counters of records produced by each source type, used to calculate the queue depth.
+    produced: Vec<Counter>,
+}
+
+impl PipeMetrics {
+    fn new(scope: &Scope) -> PipeMetrics {
+        PipeMetrics {
+            received: scope.counter("zenlog_pipeline_records_total",
+                "Number of records received by the pipeline"),
+            dropped: scope.counter("zenlog_pipeline_dropped_total",
+                "Number of records dropped by the pipeline"),
+            queue: scope.gauge("zenlog_pipeline_queue_depth",
+                "Number of records waiting for processing"),
+            latency: scope.histogram("zenlog_pipeline_processing_seconds",
+                "Record processing latency", metrics::LATENCY_BUCKETS),
+            produced: Vec::new(),
+        }
+    }
+
+    fn update_queue(&self) {
+        let produced = self.produced.iter().fold(0, |acc, counter| acc + counter.get());
+        self.queue.set(produced as isize - self.received.get() as isize);
+    }
+}
+
+/// Allows to suspend pipeline processing thread until resumed.
+///
+/// Records received while the pipeline is paused are queued.
+#[derive(Clone, Default)]
+struct Pause(Arc<(Mutex<bool>, Condvar)>);
+
+impl Pause {
+    fn set(&self, paused: bool) {
+        let &(ref lock, ref cvar) = &*self.0;
+        *lock.lock().unwrap() = paused;
+        cvar.notify_all();
+    }
+
+    fn is_paused(&self) -> bool {
+        *(self.0).0.lock().unwrap()
+    }
+
+    /// Blocks the current thread while paused.
+    fn wait(&self) {
+        let &(ref lock, ref cvar) = &*self.0;
+
+        let mut paused = lock.lock().unwrap();
+        while *paused {
+            paused = cvar.wait(paused).unwrap();
+        }
+    }
+}
+
+/// Pipeline description, that is available for introspection while the pipeline is running.
+#[derive(Clone)]
+pub struct PipeInfo {
+    name: String,
+    sources: Vec<String>,
+    filters: Vec<String>,
+    outputs: Vec<String>,
+    pause: Pause,
+}
+
+impl PipeInfo {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns types of sources attached to the pipeline.
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    /// Returns types of filters attached to the pipeline in order.
+    pub fn filters(&self) -> &[String] {
+        &self.filters
+    }
+
+    /// Returns types of outputs attached to the pipeline.
+    pub fn outputs(&self) -> &[String] {
+        &self.outputs
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+}
+
 /// Event proccessing pipeline.
 ///
 /// # Note:
@@ -115,27 +421,65 @@ impl Registry {
 ///  1. Drop pipe.
 ///  2. Drop all sources.
 ///  3. Tx is dropped -> Rx is exhaused -> Control thread is stopping.
-///  4. Drop filters.
+///  4. Flush and drop filters.
 ///  5. Drop outputs.
 struct Pipe {
+    info: PipeInfo,
     thread: Option<JoinHandle<()>>,
     sources: Vec<Box<Source>>,
     hups: Vec<Sender<()>>,
+    /// Keeps the processing thread registered in the liveness registry.
+    _probe: Probe,
 }
 
 impl Pipe {
-    fn run(cfg: &PipeConfig, registry: &Registry) -> Result<Pipe, Box<Error>> {
+    fn run(cfg: &PipeConfig, name: &str, registry: &Registry) -> Result<Pipe, Box<Error>> {
+        let base = cfg.base();
+        let scope = registry.metrics().scope(&[("pipeline", name)]);
+        let mut metrics = PipeMetrics::new(&scope);
+
         // Pipelines.
         let (tx, rx) = mpsc::channel();
 
+        let mut info = PipeInfo {
+            name: name.to_owned(),
+            sources: Vec::new(),
+            filters: Vec::new(),
+            outputs: Vec::new(),
+            pause: Pause::default(),
+        };
+
         // Start Sources.
         let mut sources = Vec::new();
+        let mut types = Vec::new();
 
         for cfg in cfg.sources() {
             trace!("starting source with config {:#?}", cfg);
 
-            let source = try!(registry.source(cfg, tx.clone()));
+            let ty = try!(Registry::ty(cfg));
+            let source = try!(registry.source(cfg, tx.clone(), &scope.with("source", ty)));
             sources.push(source);
+            info.sources.push(ty.to_owned());
+
+            // Sources of the same type share their metrics.
+            if !types.contains(&ty) {
+                let counter = scope.with("source", ty).counter("zenlog_source_records_total",
+                    "Number of records produced by the source");
+
+                types.push(ty);
+                metrics.produced.push(counter);
+            }
+        }
+
+        let mut filters = Vec::new();
+
+        for cfg in cfg.filters() {
+            trace!("constructing filter with config {:#?}", cfg);
+
+            let ty = try!(Registry::ty(cfg));
+            let filter = try!(registry.filter(cfg, name, base, &scope.with("filter", ty)));
+            filters.push(filter);
+            info.filters.push(ty.to_owned());
         }
 
         let mut outputs = Vec::new();
@@ -143,45 +487,139 @@ impl Pipe {
         for cfg in cfg.outputs() {
             trace!("constructing output with config {:#?}", cfg);
 
-            let output = try!(registry.output(cfg));
-            outputs.push(output);
+            let mut cfg = cfg.clone();
+            let ty = try!(Registry::ty(&cfg)).to_owned();
+            let route = try!(OutputRoute::extract(&mut cfg)
+                .map_err(|err| format!("invalid routing options of '{}' output: {}", ty, err)));
+            let scope = scope.with("output", &ty);
+
+            let sink = Sink {
+                name: route.name().unwrap_or(&ty).to_owned(),
+                when: route.when().cloned(),
+                output: try!(registry.output(&cfg, &scope)),
+                handled: scope.counter("zenlog_output_records_total",
+                    "Number of records successfully handled by the output"),
+                failed: scope.counter("zenlog_output_failures_total",
+                    "Number of records the output failed to handle"),
+            };
+            outputs.push(sink);
+            info.outputs.push(ty);
+        }
+
+        let mut routes = Vec::new();
+
+        for route in cfg.route() {
+            let mut ids = Vec::new();
+
+            for name in route.outputs() {
+                let mut found = false;
+
+                for (id, sink) in outputs.iter().enumerate() {
+                    if sink.name == *name {
+                        found = true;
+
+                        if !ids.contains(&id) {
+                            ids.push(id);
+                        }
+                    }
+                }
+
+                if !found {
+                    return Err(format!("route references unknown output '{}'", name).into());
+                }
+            }
+
+            routes.push(Route {
+                when: route.when().cloned(),
+                outputs: ids,
+            });
         }
 
         // Collect all hup channels.
-        let hups = outputs.iter()
-            .filter_map(|output| output.hup())
+        let hups = filters.iter()
+            .filter_map(|filter| filter.hup())
+            .chain(outputs.iter().filter_map(|sink| sink.output.hup()))
             .collect();
 
+        let mut sinks = Sinks {
+            outputs: outputs,
+            routes: routes,
+            unrouted: scope.counter("zenlog_pipeline_unrouted_total",
+                "Number of records not matched by any routing rule"),
+        };
+
+        let probe = scope.probe();
+        let guard = probe.guard();
+        let pause = info.pause.clone();
+
         let thread = thread::spawn(move || {
             debug!("started pipeline processing thread");
 
-            for record in rx {
-                debug!("processing {:?} ...", record);
+            let interval = Duration::from_secs(TICK_INTERVAL);
+            let mut ticked = Instant::now();
 
-                if record.find("message").is_none() {
-                    error!("drop '{:?}': message field required", record);
-                    continue;
-                }
+            loop {
+                match rx.recv_timeout(interval) {
+                    Ok(record) => {
+                        pause.wait();
+
+                        debug!("processing {:?} ...", record);
+
+                        let timestamp = Instant::now();
+                        metrics.received.inc();
+                        metrics.update_queue();
 
-                // TODO: Maybe add this as a filter?
-                if record.find("timestamp").is_none() {
-                    // TODO: Add (which format?).
+                        if record.find("message").is_none() {
+                            error!("drop '{:?}': message field required", record);
+                            metrics.dropped.inc();
+                        } else {
+                            // TODO: Maybe add this as a filter?
+                            if record.find("timestamp").is_none() {
+                                // TODO: Add (which format?).
+                            }
+
+                            for record in apply(&mut filters, vec![record]) {
+                                sinks.dispatch(&record);
+                            }
+                        }
+
+                        metrics.latency.observe_duration(timestamp.elapsed());
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
                 }
 
-                // TODO: Filter.
+                // Ticks are checked after each record, because the channel may be never idle.
+                if ticked.elapsed() >= interval {
+                    ticked = Instant::now();
+
+                    // Each filter gets its own time, because records emitted by previous filters
+                    // during this tick may have been already buffered by the next ones, and
+                    // timestamps must never go backwards for them.
+                    let tick = |filter: &mut Box<Filter>, out: &mut Vec<Arc<Record>>| {
+                        filter.tick(Instant::now(), out)
+                    };
 
-                for output in &mut outputs {
-                    output.handle(&record);
+                    for record in emit(&mut filters, tick) {
+                        sinks.dispatch(&record);
+                    }
                 }
             }
 
+            for record in emit(&mut filters, |filter, out| filter.flush(out)) {
+                sinks.dispatch(&record);
+            }
+
+            guard.finish();
             debug!("successfully stopped pipeline procesing thread");
         });
 
         let pipe = Pipe {
+            info: info,
             thread: Some(thread),
             sources: sources,
             hups: hups,
+            _probe: probe,
         };
 
         Ok(pipe)
@@ -199,6 +637,8 @@ impl Pipe {
 impl Drop for Pipe {
     fn drop(&mut self) {
         self.sources.clear();
+        // Paused pipeline must drain its queue before stopping.
+        self.info.pause.set(false);
 
         if let Err(err) = self.thread.take().unwrap().join() {
             error!("failed to gracefully shut down the runtime: {:?}", err);
@@ -209,6 +649,7 @@ impl Drop for Pipe {
 pub struct Runtime {
     tx: Sender<Control>,
     thread: Option<JoinHandle<()>>,
+    pipelines: Vec<PipeInfo>,
 }
 
 impl Runtime {
@@ -219,30 +660,39 @@ impl Runtime {
 
         let (tx, rx) = mpsc::channel();
 
-        let thread = try!(Runtime::init(&config, registry, rx));
+        let (thread, pipelines) = try!(Runtime::init(&config, registry, rx));
 
         let runtime = Runtime {
             tx: tx,
             thread: Some(thread),
+            pipelines: pipelines,
         };
 
         Ok(runtime)
     }
 
     fn init(config: &[PipeConfig], registry: &Registry, rx: mpsc::Receiver<Control>) ->
-        Result<JoinHandle<()>, Box<Error>>
+        Result<(JoinHandle<()>, Vec<PipeInfo>), Box<Error>>
     {
         let mut pipelines = Vec::new();
 
-        for c in config {
-            pipelines.push(try!(Pipe::run(c, registry)));
+        for (id, c) in config.iter().enumerate() {
+            let name = c.name()
+                .map(|name| name.as_str().to_owned())
+                .unwrap_or_else(|| format!("#{}", id));
+
+            pipelines.push(try!(Pipe::run(c, &name, registry)));
         }
 
         info!("started {} pipeline(s)", config.len());
 
+        let infos = pipelines.iter()
+            .map(|pipe| pipe.info.clone())
+            .collect();
+
         let thread = thread::spawn(move || Runtime::run(pipelines, rx));
 
-        Ok(thread)
+        Ok((thread, infos))
     }
 
     /// Blocks the current thread for running Zenlog Runtime.
@@ -271,6 +721,38 @@ impl Runtime {
             error!("failed to send hup signal to the runtime: {}", err);
         }
     }
+
+    /// Returns descriptions of all running pipelines.
+    pub fn pipelines(&self) -> &[PipeInfo] {
+        &self.pipelines
+    }
+
+    /// Pauses all pipelines with the given name.
+    ///
+    /// Records are queued until the pipeline is resumed.
+    pub fn pause(&mut self, name: &str) -> Result<(), Box<Error>> {
+        self.set_paused(name, true)
+    }
+
+    /// Resumes all pipelines with the given name.
+    pub fn resume(&mut self, name: &str) -> Result<(), Box<Error>> {
+        self.set_paused(name, false)
+    }
+
+    fn set_paused(&mut self, name: &str, paused: bool) -> Result<(), Box<Error>> {
+        let mut found = false;
+
+        for info in self.pipelines.iter().filter(|info| info.name == name) {
+            info.pause.set(paused);
+            found = true;
+        }
+
+        if found {
+            Ok(())
+        } else {
+            Err(format!("pipeline '{}' not found", name).into())
+        }
+    }
 }
 
 impl Drop for Runtime {
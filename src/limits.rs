@@ -0,0 +1,336 @@
+//! Record size limits, that protect outputs from oversized records.
+//!
+//! Limits are applied both by sources right after decoding and by the `truncate` filter.
+//! Pointers of all truncated fields are listed in the marker field of the record, accumulating
+//! across subsequent truncations.
+
+use std::cmp;
+
+use serde_json::{self, Value};
+
+use Record;
+use config::LimitsConfig;
+
+/// Strings are never shortened below this length to fit the record size limit.
+const MIN_STRING_LENGTH: usize = 64;
+
+/// Fields kept when shortening strings is not enough to fit the record size limit.
+///
+/// The marker field is always kept as well.
+const ESSENTIAL: &'static [&'static str] = &["message", "timestamp", "severity", "tags"];
+
+/// Returns approximate size of the encoded value, ignoring escaping.
+fn size(value: &Value) -> usize {
+    match *value {
+        Value::Null => 4,
+        Value::Bool(..) => 5,
+        Value::I64(..) | Value::U64(..) | Value::F64(..) => 20,
+        Value::String(ref val) => val.len() + 2,
+        Value::Array(ref vec) => vec.iter().fold(2, |acc, val| acc + size(val) + 1),
+        Value::Object(ref map) => {
+            map.iter().fold(2, |acc, (key, val)| acc + key.len() + 4 + size(val))
+        }
+    }
+}
+
+/// Returns the length of the longest string in the given value.
+fn longest(value: &Value) -> usize {
+    match *value {
+        Value::String(ref val) => val.len(),
+        Value::Array(ref vec) => vec.iter().fold(0, |acc, val| cmp::max(acc, longest(val))),
+        Value::Object(ref map) => map.values().fold(0, |acc, val| cmp::max(acc, longest(val))),
+        _ => 0,
+    }
+}
+
+fn is_nested(value: &Value) -> bool {
+    match *value {
+        Value::Array(..) | Value::Object(..) => true,
+        _ => false,
+    }
+}
+
+/// Truncates the string to at most `max` bytes, respecting character boundaries.
+fn truncate(val: &mut String, max: usize) {
+    let mut end = max;
+    while !val.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    val.truncate(end);
+}
+
+/// Escapes the object key to be used as a JSON pointer segment.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+pub struct Limits {
+    record_size: Option<usize>,
+    string_length: Option<usize>,
+    array_length: Option<usize>,
+    depth: Option<usize>,
+    marker: String,
+}
+
+impl Limits {
+    pub fn new(cfg: &LimitsConfig) -> Limits {
+        Limits {
+            record_size: cfg.max_record_size(),
+            string_length: cfg.max_string_length(),
+            array_length: cfg.max_array_length(),
+            // The record itself can never be replaced.
+            depth: cfg.max_depth().map(|max| cmp::max(max, 1)),
+            marker: cfg.marker().to_owned(),
+        }
+    }
+
+    /// Returns whether no limits are configured.
+    pub fn is_empty(&self) -> bool {
+        self.record_size.is_none() && self.string_length.is_none() &&
+            self.array_length.is_none() && self.depth.is_none()
+    }
+
+    /// Returns whether the given record exceeds any of the limits.
+    pub fn exceeds(&self, record: &Record) -> bool {
+        self.record_size.map_or(false, |max| size(record) > max) || self.violates(record, 1)
+    }
+
+    fn violates(&self, value: &Value, depth: usize) -> bool {
+        match *value {
+            Value::String(ref val) => self.string_length.map_or(false, |max| val.len() > max),
+            Value::Array(ref vec) => {
+                self.too_deep(depth) || self.array_length.map_or(false, |max| vec.len() > max) ||
+                    vec.iter().any(|val| self.violates(val, depth + 1))
+            }
+            Value::Object(ref map) => {
+                self.too_deep(depth) || map.values().any(|val| self.violates(val, depth + 1))
+            }
+            _ => false,
+        }
+    }
+
+    fn too_deep(&self, depth: usize) -> bool {
+        self.depth.map_or(false, |max| depth > max)
+    }
+
+    /// Truncates the given record to fit the limits.
+    ///
+    /// Pointers of truncated fields are merged into the existing marker, if any. Returns whether
+    /// anything was truncated.
+    pub fn apply(&self, record: &mut Record) -> bool {
+        let mut truncated = Vec::new();
+        let mut path = String::new();
+
+        // The marker is set aside, so that its entries are neither truncated nor lost.
+        let marker = record.as_object_mut().and_then(|map| map.remove(&self.marker));
+
+        self.walk(record, &mut path, 1, self.string_length, &mut truncated);
+
+        if let Some(max) = self.record_size {
+            let mut length = self.string_length.unwrap_or_else(|| longest(record));
+
+            while size(record) > max && length > MIN_STRING_LENGTH {
+                length /= 2;
+                self.walk(record, &mut path, 1, Some(length), &mut truncated);
+            }
+
+            if size(record) > max {
+                if let Some(map) = record.as_object_mut() {
+                    let keys: Vec<String> = map.keys()
+                        .filter(|key| !ESSENTIAL.contains(&key.as_str()))
+                        .cloned()
+                        .collect();
+
+                    for key in keys {
+                        map.remove(&key);
+                        truncated.push(format!("/{}", escape(&key)));
+                    }
+                }
+            }
+        }
+
+        if truncated.is_empty() {
+            if let (Some(marker), Some(map)) = (marker, record.as_object_mut()) {
+                map.insert(self.marker.clone(), marker);
+            }
+
+            return false;
+        }
+
+        if let Some(Value::Array(vec)) = marker {
+            truncated.extend(vec.into_iter().filter_map(|val| match val {
+                Value::String(val) => Some(val),
+                _ => None,
+            }));
+        }
+
+        truncated.sort();
+        truncated.dedup();
+
+        if let Some(map) = record.as_object_mut() {
+            map.insert(self.marker.clone(),
+                Value::Array(truncated.into_iter().map(Value::String).collect()));
+        }
+
+        true
+    }
+
+    fn walk(&self, value: &mut Value, path: &mut String, depth: usize,
+        string_length: Option<usize>, truncated: &mut Vec<String>)
+    {
+        if self.too_deep(depth) && is_nested(value) {
+            let encoded = serde_json::to_string(&*value).unwrap_or_else(|_| String::new());
+            *value = Value::String(encoded);
+            truncated.push(path.clone());
+        }
+
+        match *value {
+            Value::String(ref mut val) => {
+                if let Some(max) = string_length {
+                    if val.len() > max {
+                        truncate(val, max);
+                        truncated.push(path.clone());
+                    }
+                }
+            }
+            Value::Array(ref mut vec) => {
+                if let Some(max) = self.array_length {
+                    if vec.len() > max {
+                        vec.truncate(max);
+                        truncated.push(path.clone());
+                    }
+                }
+
+                for (id, val) in vec.iter_mut().enumerate() {
+                    let len = path.len();
+                    path.push('/');
+                    path.push_str(&id.to_string());
+                    self.walk(val, path, depth + 1, string_length, truncated);
+                    path.truncate(len);
+                }
+            }
+            Value::Object(ref mut map) => {
+                for (key, val) in map.iter_mut() {
+                    let len = path.len();
+                    path.push('/');
+                    path.push_str(&escape(key));
+                    self.walk(val, path, depth + 1, string_length, truncated);
+                    path.truncate(len);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use serde_json::{self, Value};
+
+    use config::LimitsConfig;
+
+    use super::Limits;
+
+    fn limits(cfg: &str) -> Limits {
+        let cfg: LimitsConfig = serde_json::from_str(cfg).unwrap();
+        Limits::new(&cfg)
+    }
+
+    fn json(val: &str) -> Value {
+        serde_json::from_str(val).unwrap()
+    }
+
+    #[test]
+    fn truncate_strings() {
+        let limits = limits(r#"{"max_string_length": 4}"#);
+        let mut record = json(r#"{"message": "hello world", "pid": 42}"#);
+
+        assert!(limits.exceeds(&record));
+        assert!(limits.apply(&mut record));
+        assert_eq!(json(r#"{"message": "hell", "pid": 42, "_truncated": ["/message"]}"#), record);
+    }
+
+    #[test]
+    fn truncate_strings_at_char_boundary() {
+        let limits = limits(r#"{"max_string_length": 3}"#);
+        let mut record = json(r#"{"message": "привет"}"#);
+
+        assert!(limits.apply(&mut record));
+        assert_eq!(Some("п"), record.find("message").and_then(|val| val.as_string()));
+    }
+
+    #[test]
+    fn truncate_arrays() {
+        let limits = limits(r#"{"max_array_length": 2}"#);
+        let mut record = json(r#"{"items": ["a", "b", "c"]}"#);
+
+        assert!(limits.apply(&mut record));
+        assert_eq!(json(r#"{"items": ["a", "b"], "_truncated": ["/items"]}"#), record);
+    }
+
+    #[test]
+    fn encode_values_beyond_depth() {
+        let limits = limits(r#"{"max_depth": 2}"#);
+        let mut record = json(r#"{"a": {"b": {"c": 1}, "d": 2}, "e": [3]}"#);
+
+        assert!(limits.exceeds(&record));
+        assert!(limits.apply(&mut record));
+        assert_eq!(json(r#"{"a": {"b": "{\"c\":1}", "d": 2}, "e": [3], "_truncated": ["/a/b"]}"#),
+            record);
+    }
+
+    #[test]
+    fn shorten_strings_to_fit_record_size() {
+        let limits = limits(r#"{"max_record_size": 200}"#);
+        let message: String = iter::repeat('x').take(1000).collect();
+        let mut record = Value::Object(vec![("message".to_owned(), Value::String(message))]
+            .into_iter()
+            .collect());
+
+        assert!(limits.apply(&mut record));
+        assert_eq!(Some(125), record.find("message").and_then(|val| val.as_string())
+            .map(|val| val.len()));
+        assert!(!limits.exceeds(&record));
+    }
+
+    #[test]
+    fn remove_fields_to_fit_record_size() {
+        let limits = limits(r#"{"max_record_size": 100}"#);
+        let mut record = json(r#"{
+            "message": "m",
+            "severity": "info",
+            "tags": ["t"],
+            "_truncated": ["/old"],
+            "payload": [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20]
+        }"#);
+
+        assert!(limits.apply(&mut record));
+        assert_eq!(json(r#"{
+            "message": "m",
+            "severity": "info",
+            "tags": ["t"],
+            "_truncated": ["/old", "/payload"]
+        }"#), record);
+    }
+
+    #[test]
+    fn merge_existing_marker() {
+        let limits = limits(r#"{"max_string_length": 4}"#);
+        let mut record = json(r#"{"message": "hello", "_truncated": ["/a", "/message", 1]}"#);
+
+        assert!(limits.apply(&mut record));
+        assert_eq!(json(r#"{"message": "hell", "_truncated": ["/a", "/message"]}"#), record);
+    }
+
+    #[test]
+    fn keep_marker_intact_within_limits() {
+        let limits = limits(r#"{"max_string_length": 4}"#);
+        let mut record = json(r#"{"message": "hi", "_truncated": ["/a", 1]}"#);
+
+        assert!(!limits.apply(&mut record));
+        assert_eq!(json(r#"{"message": "hi", "_truncated": ["/a", 1]}"#), record);
+    }
+}
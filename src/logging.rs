@@ -5,9 +5,11 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 
 use chrono;
 use libc;
-use log::{self, LogRecord, LogLevel, LogMetadata};
+use log::{self, LogRecord, LogLevel, LogLevelFilter, LogMetadata};
 use termion::color::{self, AnsiValue};
 
+use severity::Severity;
+
 fn level_as_usize(level: LogLevel) -> usize {
     match level {
         LogLevel::Error => 0,
@@ -114,14 +116,13 @@ impl AsLogLevel for usize {
 
 impl AsLogLevel for str {
     fn as_level(&self) -> Option<LogLevel> {
-        match self {
-            "ERROR" => Some(LogLevel::Error),
-            "WARN"  => Some(LogLevel::Warn),
-            "INFO"  => Some(LogLevel::Info),
-            "DEBUG" => Some(LogLevel::Debug),
-            "TRACE" => Some(LogLevel::Trace),
-            _ => None,
-        }
+        self.parse::<Severity>().ok().and_then(|sev| sev.as_level())
+    }
+}
+
+impl AsLogLevel for Severity {
+    fn as_level(&self) -> Option<LogLevel> {
+        self.as_usize().as_level()
     }
 }
 
@@ -132,7 +133,9 @@ pub fn init<T: ?Sized + AsLogLevel>(level: &T) -> Result<Arc<AtomicUsize>, Box<E
 
     let clone = lvl.clone();
     log::set_logger(|max| {
-        max.set(level.to_log_level_filter());
+        // The actual filtering is done by the logger itself, because the severity can be changed
+        // at runtime in both directions.
+        max.set(LogLevelFilter::Trace);
         Box::new(Logger::new(clone))
     })
     .map(|_| lvl)
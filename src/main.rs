@@ -1,17 +1,122 @@
 #[macro_use] extern crate log;
+#[macro_use] extern crate chan;
 extern crate chan_signal as signal;
 
 extern crate zenlog;
 
-use std::sync::atomic::Ordering;
+use std::env;
+use std::error::Error;
+use std::io::{self, Write};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use signal::Signal;
 
 use zenlog::{Registry, Runtime, RuntimeConfig};
-use zenlog::logging::{AsLogLevel, AsUsize};
+use zenlog::admin::{self, Command, Request};
+use zenlog::http::Server;
+use zenlog::metrics::Reporter;
+use zenlog::severity::Severity;
+
+const FILENAME: &'static str = ".zenlog.json";
+
+/// Rereads the config file and recreates the runtime.
+///
+/// The current runtime is left untouched on any error.
+fn reload(registry: &Registry, runtime: &mut Option<Runtime>) -> Result<(), Box<Error>> {
+    let cfg = try!(RuntimeConfig::from(FILENAME));
+
+    let rt = try!(Runtime::new(cfg.pipelines(), registry)
+        .map_err(|err| format!("failed to create runtime: {}", err)));
+
+    *runtime = Some(rt);
+
+    Ok(())
+}
+
+fn set_severity(severity: &AtomicUsize, level: Severity) {
+    severity.store(level.as_usize(), Ordering::SeqCst);
+    info!("severity level is now {}", level);
+}
+
+/// Executes the given admin command, returning the response body.
+fn execute(command: Command, registry: &Registry, runtime: &mut Option<Runtime>,
+    severity: &AtomicUsize) -> Result<String, Box<Error>>
+{
+    match command {
+        Command::Reload => {
+            try!(reload(registry, runtime));
+            Ok(String::new())
+        }
+        Command::Reopen => {
+            runtime.as_mut().unwrap().hup();
+            Ok(String::new())
+        }
+        Command::Severity(level) => {
+            set_severity(severity, level);
+            Ok(String::new())
+        }
+        Command::Pipelines => {
+            let mut result = String::new();
+
+            for info in runtime.as_ref().unwrap().pipelines() {
+                result.push_str(&format!("{}{}: sources=[{}] filters=[{}] outputs=[{}]\n",
+                    info.name(),
+                    if info.is_paused() { " (paused)" } else { "" },
+                    info.sources().join(", "),
+                    info.filters().join(", "),
+                    info.outputs().join(", ")
+                ));
+            }
+
+            Ok(result)
+        }
+        Command::Stats => Ok(registry.metrics().render()),
+        Command::Pause(name) => {
+            try!(runtime.as_mut().unwrap().pause(&name));
+            Ok(String::new())
+        }
+        Command::Resume(name) => {
+            try!(runtime.as_mut().unwrap().resume(&name));
+            Ok(String::new())
+        }
+        // Handled by the main loop after replying.
+        Command::Shutdown => Ok(String::new()),
+    }
+}
+
+/// Admin CLI client.
+///
+/// Usage: `zenlog admin [--socket PATH] COMMAND [ARG]`. The socket path is read from the config
+/// file unless specified explicitly.
+fn client(args: &[String]) -> Result<String, Box<Error>> {
+    let (path, args) = match args.first().map(|v| &v[..]) {
+        Some("--socket") if args.len() > 1 => (args[1].clone(), &args[2..]),
+        _ => {
+            let cfg = try!(RuntimeConfig::from(FILENAME));
+            let path = try!(cfg.admin().ok_or("admin socket is not configured"));
+
+            (path.path().to_owned(), args)
+        }
+    };
+
+    admin::call(path, &args.join(" "))
+}
 
 fn main() {
-    let filename = ".zenlog.json";
+    let args: Vec<String> = env::args().collect();
+
+    if args.get(1).map(|v| &v[..]) == Some("admin") {
+        match client(&args[2..]) {
+            Ok(body) => print!("{}", body),
+            Err(err) => {
+                let _ = writeln!(io::stderr(), "error: {}", err);
+                process::exit(1);
+            }
+        }
+
+        return;
+    }
 
     // List of signals we want to listen.
     // - INT and TERM - for graceful termination.
@@ -27,13 +132,39 @@ fn main() {
     let sigset = [Signal::INT, Signal::TERM, Signal::HUP, Signal::USR1, Signal::USR2];
     let listener = signal::notify(&sigset);
 
-    let cfg = RuntimeConfig::from(filename)
+    let cfg = RuntimeConfig::from(FILENAME)
         .expect("failed to read configuration file");
 
-    let severity = zenlog::logging::init(cfg.severity())
+    let severity = zenlog::logging::init(&cfg.severity())
         .expect("failed to initialize the logging system");
 
-    let registry = Registry::new();
+    // Admin requests are executed here, in the main thread, along with signals. Note that we
+    // must keep the sender alive even if the admin socket is disabled, otherwise the receiver
+    // will be immediately closed.
+    let (admin_tx, admin_rx) = chan::sync::<Request>(0);
+
+    // Sources request the shutdown without waiting, because the main loop may be busy dropping
+    // the runtime they belong to, so the channel is unbounded.
+    let (shutdown_tx, shutdown_rx) = chan::async::<()>();
+
+    let registry = Registry::new(shutdown_tx);
+
+    // Metrics are shared between runtime reloads, so does the reporter.
+    let _reporter = cfg.metrics()
+        .map(|cfg| Reporter::new(registry.metrics().clone(), cfg.interval()));
+
+    let _server = cfg.http()
+        .map(|cfg| {
+            cfg.endpoint()
+                .and_then(|endpoint| Server::new(&endpoint, registry.metrics().clone()))
+                .expect("failed to start HTTP listener")
+        });
+
+    let admin = cfg.admin()
+        .map(|cfg| {
+            admin::Server::new(cfg.path(), admin_tx.clone())
+                .expect("failed to start admin socket listener")
+        });
 
     info!("starting Zenlog");
     info!("special signal handlers are set for {:?} signals", sigset);
@@ -41,54 +172,61 @@ fn main() {
     let mut runtime = Some(Runtime::new(cfg.pipelines(), &registry)
         .expect("failed to create runtime"));
 
-    for signal in listener {
-        info!("caught {:?} signal", signal);
-        match signal {
-            Signal::HUP => {
-                match RuntimeConfig::from(filename) {
-                    Ok(cfg) => {
-                        runtime = match Runtime::new(cfg.pipelines(), &registry) {
-                            Ok(runtime) => Some(runtime),
-                            Err(err) => {
-                                error!("failed to create runtime: {:?}", err);
-                                continue;
-                            }
-                        };
+    loop {
+        chan_select! {
+            listener.recv() -> signal => {
+                let signal = signal.expect("signal listener must outlive the main loop");
+
+                info!("caught {:?} signal", signal);
+                match signal {
+                    Signal::HUP => {
+                        if let Err(err) = reload(&registry, &mut runtime) {
+                            error!("{}", err);
+                        }
                     }
-                    Err(err) => {
-                        error!("failed to read {}: {:?}", filename, err);
+                    Signal::USR1 => {
+                        // Always valid.
+                        runtime.as_mut().unwrap().hup();
                     }
-                }
-            }
-            Signal::USR1 => {
-                // Always valid.
-                runtime.as_mut().unwrap().hup();
-            }
-            Signal::USR2 => {
-                match RuntimeConfig::from(filename) {
-                    Ok(cfg) => {
-                        match cfg.severity().as_level() {
-                            Some(lvl) => {
-                                severity.store(lvl.as_usize(), Ordering::SeqCst);
-                                info!("severity level is now {}", cfg.severity());
-                            }
-                            None => {
-                                warn!("failed to reinitialize the logging system");
-                            }
+                    Signal::USR2 => {
+                        match RuntimeConfig::from(FILENAME) {
+                            Ok(cfg) => set_severity(&severity, cfg.severity()),
+                            Err(err) => error!("{}", err),
                         }
                     }
-                    Err(err) => {
-                        error!("failed to read {}: {:?}", filename, err);
+                    signal => {
+                        info!("caught {:?} signal, shutting down", signal);
+                        break;
                     }
                 }
-            }
-            signal => {
-                info!("caught {:?} signal, shutting down", signal);
+            },
+            admin_rx.recv() -> request => {
+                let request = request.expect("admin channel must outlive the main loop");
+
+                info!("executing {:?} admin command", request.command);
+                let shutdown = request.command == Command::Shutdown;
+                let result = execute(request.command, &registry, &mut runtime, &severity)
+                    .map_err(|err| err.to_string());
+
+                if let Err(err) = request.reply.send(result) {
+                    warn!("failed to reply to admin request: {}", err);
+                }
+
+                if shutdown {
+                    info!("shutting down by admin request");
+                    break;
+                }
+            },
+            shutdown_rx.recv() => {
+                info!("shutting down by source request");
                 break;
-            }
+            },
         }
     }
 
+    // Stop accepting admin requests first, because nobody is going to execute them.
+    drop(admin);
+
     runtime.unwrap();
     info!("Zenlog has been successfully stopped");
 }
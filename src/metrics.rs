@@ -0,0 +1,487 @@
+//! Self-metrics subsystem.
+//!
+//! All metrics are stored in a shared registry, which outlives runtime reloads, so counters stay
+//! monotonic. Components obtain metrics handles once at construction and update them lock-free
+//! (except histograms) in the hot path.
+//!
+//! The collected data can be rendered either as a compact single line suitable for periodic
+//! self-reporting via logs or in the Prometheus text exposition format.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use health::{Health, Probe};
+
+/// Default histogram buckets for measuring processing latency, in seconds.
+pub const LATENCY_BUCKETS: &'static [f64] = &[
+    0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0,
+];
+
+type Labels = Vec<(String, String)>;
+
+/// Monotonically increasing counter.
+#[derive(Clone, Default)]
+pub struct Counter(Arc<AtomicUsize>);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, val: usize) {
+        self.0.fetch_add(val, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A value that can arbitrarily go up and down.
+#[derive(Clone, Default)]
+pub struct Gauge(Arc<AtomicIsize>);
+
+impl Gauge {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, val: isize) {
+        self.0.store(val, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> isize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+struct Buckets {
+    bounds: Vec<f64>,
+    counts: Vec<usize>,
+    sum: f64,
+    count: usize,
+}
+
+/// Samples observations and counts them in configurable buckets.
+#[derive(Clone)]
+pub struct Histogram(Arc<Mutex<Buckets>>);
+
+impl Histogram {
+    fn new(bounds: &[f64]) -> Histogram {
+        let buckets = Buckets {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        };
+
+        Histogram(Arc::new(Mutex::new(buckets)))
+    }
+
+    pub fn observe(&self, val: f64) {
+        let mut buckets = self.0.lock().unwrap();
+
+        if let Some(id) = buckets.bounds.iter().position(|&bound| val <= bound) {
+            buckets.counts[id] += 1;
+        }
+
+        buckets.sum += val;
+        buckets.count += 1;
+    }
+
+    /// Observes the given duration in seconds.
+    pub fn observe_duration(&self, val: Duration) {
+        self.observe(val.as_secs() as f64 + val.subsec_nanos() as f64 / 1e9);
+    }
+}
+
+#[derive(Clone)]
+enum Metric {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+struct Family {
+    help: String,
+    ty: &'static str,
+    series: BTreeMap<Labels, Metric>,
+}
+
+/// Shared metrics registry.
+///
+/// Cloning is cheap and results in a handle to the same registry.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    families: Arc<Mutex<BTreeMap<String, Family>>>,
+    health: Health,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Returns the liveness registry of threads, which report their metrics here.
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    /// Returns a scope, which attaches the given labels to every metric created through it.
+    pub fn scope(&self, labels: &[(&str, &str)]) -> Scope {
+        Scope {
+            metrics: self.clone(),
+            labels: to_labels(labels),
+        }
+    }
+
+    /// Returns a counter with the given name and labels, registering it if required.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if there is already registered metric of other type with the same name.
+    pub fn counter(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Counter {
+        let labels = to_labels(labels);
+
+        let metric = self.get_or_insert(name, help, "counter", labels, || {
+            Metric::Counter(Counter::default())
+        });
+
+        match metric {
+            Metric::Counter(counter) => counter,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns a gauge with the given name and labels, registering it if required.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if there is already registered metric of other type with the same name.
+    pub fn gauge(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Gauge {
+        let labels = to_labels(labels);
+
+        let metric = self.get_or_insert(name, help, "gauge", labels, || {
+            Metric::Gauge(Gauge::default())
+        });
+
+        match metric {
+            Metric::Gauge(gauge) => gauge,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns a histogram with the given name, labels and buckets, registering it if required.
+    ///
+    /// Buckets are ignored if the histogram has already been registered.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if there is already registered metric of other type with the same name.
+    pub fn histogram(&self, name: &str, help: &str, labels: &[(&str, &str)], buckets: &[f64]) ->
+        Histogram
+    {
+        let labels = to_labels(labels);
+
+        let metric = self.get_or_insert(name, help, "histogram", labels, || {
+            Metric::Histogram(Histogram::new(buckets))
+        });
+
+        match metric {
+            Metric::Histogram(histogram) => histogram,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Registers a family of counters with the given name without any series.
+    ///
+    /// Unlike `counter` returns an error if there is already registered metric of other type with
+    /// the same name, which allows to validate user-defined metrics on construction.
+    pub fn declare_counter(&self, name: &str, help: &str) -> Result<(), String> {
+        self.declare(name, help, "counter")
+    }
+
+    /// Registers a family of histograms with the given name without any series.
+    ///
+    /// Unlike `histogram` returns an error if there is already registered metric of other type
+    /// with the same name.
+    pub fn declare_histogram(&self, name: &str, help: &str) -> Result<(), String> {
+        self.declare(name, help, "histogram")
+    }
+
+    /// Returns the number of series of the metric with the given name across all scopes.
+    pub fn series_count(&self, name: &str) -> usize {
+        let families = self.families.lock().unwrap();
+
+        families.get(name).map_or(0, |family| family.series.len())
+    }
+
+    /// Returns whether the metric with the given name and labels is already registered.
+    pub fn has_series(&self, name: &str, labels: &[(&str, &str)]) -> bool {
+        let families = self.families.lock().unwrap();
+
+        families.get(name).map_or(false, |family| family.series.contains_key(&to_labels(labels)))
+    }
+
+    fn declare(&self, name: &str, help: &str, ty: &'static str) -> Result<(), String> {
+        let mut families = self.families.lock().unwrap();
+
+        Metrics::family(&mut *families, name, help, ty).map(|_| ())
+    }
+
+    fn family<'a>(families: &'a mut BTreeMap<String, Family>, name: &str, help: &str,
+        ty: &'static str) -> Result<&'a mut Family, String>
+    {
+        let family = families.entry(name.to_owned()).or_insert_with(|| {
+            Family {
+                help: help.to_owned(),
+                ty: ty,
+                series: BTreeMap::new(),
+            }
+        });
+
+        if family.ty != ty {
+            return Err(format!("metric '{}' is already registered as {}", name, family.ty));
+        }
+
+        Ok(family)
+    }
+
+    fn get_or_insert<F>(&self, name: &str, help: &str, ty: &'static str, labels: Labels, f: F) ->
+        Metric
+        where F: FnOnce() -> Metric
+    {
+        let mut families = self.families.lock().unwrap();
+
+        match Metrics::family(&mut *families, name, help, ty) {
+            Ok(family) => family.series.entry(labels).or_insert_with(f).clone(),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Renders all metrics in a compact single-line form, suitable for logging.
+    ///
+    /// Histograms are rendered as their observations count and sum.
+    pub fn summary(&self) -> String {
+        let families = self.families.lock().unwrap();
+
+        let mut result = String::new();
+        for (name, family) in families.iter() {
+            for (labels, metric) in &family.series {
+                if !result.is_empty() {
+                    result.push(' ');
+                }
+
+                write!(result, "{}{}=", name, format_labels(labels, None)).unwrap();
+
+                match *metric {
+                    Metric::Counter(ref counter) => write!(result, "{}", counter.get()).unwrap(),
+                    Metric::Gauge(ref gauge) => write!(result, "{}", gauge.get()).unwrap(),
+                    Metric::Histogram(ref histogram) => {
+                        let buckets = histogram.0.lock().unwrap();
+                        write!(result, "{}/{}", buckets.count, buckets.sum).unwrap();
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Renders all metrics using the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+
+        let mut result = String::new();
+        for (name, family) in families.iter() {
+            writeln!(result, "# HELP {} {}", name, escape_help(&family.help)).unwrap();
+            writeln!(result, "# TYPE {} {}", name, family.ty).unwrap();
+
+            for (labels, metric) in &family.series {
+                match *metric {
+                    Metric::Counter(ref counter) => {
+                        let labels = format_labels(labels, None);
+                        writeln!(result, "{}{} {}", name, labels, counter.get()).unwrap();
+                    }
+                    Metric::Gauge(ref gauge) => {
+                        let labels = format_labels(labels, None);
+                        writeln!(result, "{}{} {}", name, labels, gauge.get()).unwrap();
+                    }
+                    Metric::Histogram(ref histogram) => {
+                        let buckets = histogram.0.lock().unwrap();
+
+                        let mut cumulative = 0;
+                        for (bound, count) in buckets.bounds.iter().zip(buckets.counts.iter()) {
+                            cumulative += *count;
+                            writeln!(result, "{}_bucket{} {}",
+                                name,
+                                format_labels(labels, Some(&bound.to_string())),
+                                cumulative
+                            ).unwrap();
+                        }
+
+                        writeln!(result, "{}_bucket{} {}",
+                            name, format_labels(labels, Some("+Inf")), buckets.count).unwrap();
+                        writeln!(result, "{}_sum{} {}",
+                            name, format_labels(labels, None), buckets.sum).unwrap();
+                        writeln!(result, "{}_count{} {}",
+                            name, format_labels(labels, None), buckets.count).unwrap();
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// A view of the metrics registry with a predefined set of labels.
+#[derive(Clone)]
+pub struct Scope {
+    metrics: Metrics,
+    labels: Labels,
+}
+
+impl Scope {
+    /// Returns a new scope, extended with the given label.
+    pub fn with(&self, key: &str, value: &str) -> Scope {
+        let mut labels = self.labels.clone();
+        labels.push((key.to_owned(), value.to_owned()));
+
+        Scope {
+            metrics: self.metrics.clone(),
+            labels: labels,
+        }
+    }
+
+    pub fn counter(&self, name: &str, help: &str) -> Counter {
+        self.metrics.counter(name, help, &self.labels())
+    }
+
+    pub fn gauge(&self, name: &str, help: &str) -> Gauge {
+        self.metrics.gauge(name, help, &self.labels())
+    }
+
+    pub fn histogram(&self, name: &str, help: &str, buckets: &[f64]) -> Histogram {
+        self.metrics.histogram(name, help, &self.labels(), buckets)
+    }
+
+    pub fn declare_counter(&self, name: &str, help: &str) -> Result<(), String> {
+        self.metrics.declare_counter(name, help)
+    }
+
+    pub fn declare_histogram(&self, name: &str, help: &str) -> Result<(), String> {
+        self.metrics.declare_histogram(name, help)
+    }
+
+    pub fn series_count(&self, name: &str) -> usize {
+        self.metrics.series_count(name)
+    }
+
+    /// Returns whether the metric with the given name and labels of this scope is registered.
+    pub fn has_series(&self, name: &str) -> bool {
+        self.metrics.has_series(name, &self.labels())
+    }
+
+    /// Returns whether this scope attaches a label with the given name.
+    pub fn has_label(&self, key: &str) -> bool {
+        self.labels.iter().any(|&(ref name, _)| name == key)
+    }
+
+    /// Registers a thread, identified by labels of this scope, in the liveness registry.
+    pub fn probe(&self) -> Probe {
+        self.metrics.health.register(format_labels(&self.labels, None))
+    }
+
+    fn labels(&self) -> Vec<(&str, &str)> {
+        self.labels.iter()
+            .map(|&(ref key, ref value)| (&key[..], &value[..]))
+            .collect()
+    }
+}
+
+/// Periodically writes the metrics summary into the log.
+pub struct Reporter {
+    tx: mpsc::Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Reporter {
+    pub fn new(metrics: Metrics, interval: Duration) -> Reporter {
+        let (tx, rx) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = rx.recv_timeout(interval) {
+                info!("metrics: {}", metrics.summary());
+            }
+        });
+
+        Reporter {
+            tx: tx,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Reporter {
+    fn drop(&mut self) {
+        if let Err(err) = self.tx.send(()) {
+            error!("failed to send stop event to the metrics reporter: {}", err);
+        }
+
+        if let Err(err) = self.thread.take().expect("thread must exist").join() {
+            error!("failed to gracefully stop the metrics reporter: {:?}", err);
+        }
+    }
+}
+
+fn to_labels(labels: &[(&str, &str)]) -> Labels {
+    labels.iter()
+        .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+        .collect()
+}
+
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+    if labels.is_empty() && le.is_none() {
+        return String::new();
+    }
+
+    let mut result = String::from("{");
+    for (id, &(ref key, ref value)) in labels.iter().enumerate() {
+        if id > 0 {
+            result.push(',');
+        }
+
+        write!(result, "{}=\"{}\"", key, escape_label(value)).unwrap();
+    }
+
+    if let Some(le) = le {
+        if !labels.is_empty() {
+            result.push(',');
+        }
+
+        write!(result, "le=\"{}\"", le).unwrap();
+    }
+
+    result.push('}');
+    result
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn escape_help(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+}
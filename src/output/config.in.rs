@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+
+use expr::Predicate;
+use filter::pointer::Pointer;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DevConfig {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricType {
+    Counter,
+    Histogram,
+}
+
+impl FromStr for MetricType {
+    type Err = String;
+
+    fn from_str(val: &str) -> Result<MetricType, String> {
+        match val {
+            "counter" => Ok(MetricType::Counter),
+            "histogram" => Ok(MetricType::Histogram),
+            _ => Err(format!("invalid metric type '{}', must be either 'counter' or 'histogram'",
+                val)),
+        }
+    }
+}
+
+impl Deserialize for MetricType {
+    fn deserialize<D>(de: &mut D) -> Result<MetricType, D::Error>
+        where D: Deserializer
+    {
+        let val = try!(String::deserialize(de));
+        val.parse().map_err(de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricConfig {
+    /// Metric name, i.e. "app_errors_total".
+    name: String,
+    #[serde(rename="type")]
+    ty: MetricType,
+    /// Metric description, shown on the metrics endpoint.
+    help: Option<String>,
+    /// Only matching records are accounted, all records by default.
+    when: Option<Predicate>,
+    /// Numeric field to observe, required for histograms.
+    ///
+    /// Counters are incremented by the field value if specified, or by one otherwise. Negative
+    /// and fractional counter values are dropped.
+    field: Option<Pointer>,
+    /// Label names mapped to fields, which values they take.
+    #[serde(default)]
+    labels: BTreeMap<String, Pointer>,
+    /// Histogram buckets, the Prometheus default ones by default.
+    buckets: Option<Vec<f64>>,
+}
+
+impl MetricConfig {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn ty(&self) -> MetricType {
+        self.ty
+    }
+
+    pub fn help(&self) -> &str {
+        self.help.as_ref().map_or("User-defined metric", |help| help.as_str())
+    }
+
+    pub fn when(&self) -> Option<&Predicate> {
+        self.when.as_ref()
+    }
+
+    pub fn field(&self) -> Option<&Pointer> {
+        self.field.as_ref()
+    }
+
+    pub fn labels(&self) -> &BTreeMap<String, Pointer> {
+        &self.labels
+    }
+
+    pub fn buckets(&self) -> Vec<f64> {
+        self.buckets.clone().unwrap_or_else(|| {
+            vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExporterConfig {
+    metrics: Vec<MetricConfig>,
+    /// Maximum number of label combinations per metric, 1000 by default.
+    ///
+    /// Series are shared by all exporters and kept across reloads, so all of them count towards
+    /// the limit.
+    ///
+    /// Records with new combinations are not accounted when the limit is reached.
+    max_series: Option<usize>,
+}
+
+impl ExporterConfig {
+    pub fn metrics(&self) -> &Vec<MetricConfig> {
+        &self.metrics
+    }
+
+    pub fn max_series(&self) -> usize {
+        self.max_series.unwrap_or(1000)
+    }
+}
@@ -1,13 +1,20 @@
+use std::error::Error;
 use std::io::{stdout, Write};
+use std::iter;
 use std::sync::Arc;
 
 use chrono::{DateTime, UTC};
 use chrono::naive::datetime::NaiveDateTime;
 
+use serde_json::Value;
+
 use termion::color::{self, AnsiValue};
 
 use {Config, Record};
+use config;
+use metrics::Scope;
 use output::{Output, OutputFactory};
+use output::config::DevConfig;
 
 /// Eye-candy output that is used mainly both for demonstrating `Zen` features and for developing
 /// applications.
@@ -17,14 +24,11 @@ use output::{Output, OutputFactory};
 /// # Note
 ///
 /// This output is activated automatically when `Zen` is executed without arguments.
+///
+/// Traces assembled by the `trace` filter are printed as an indented tree of their records,
+/// following the span hierarchy.
 pub struct Dev;
 
-impl Dev {
-    fn new() -> Dev {
-        Dev
-    }
-}
-
 const NANOSECONDS_IN_SECOND: i64 = 1000000000;
 
 fn color_from_severity(sev: char) -> color::Fg<AnsiValue> {
@@ -38,12 +42,31 @@ fn color_from_severity(sev: char) -> color::Fg<AnsiValue> {
     color::Fg(rgb)
 }
 
-impl Output for Dev {
-    fn handle(&mut self, record: &Arc<Record>) {
-        let wr = stdout();
-        let mut wr = wr.lock();
+impl Dev {
+    fn new() -> Dev {
+        Dev
+    }
+
+    /// Writes the given span tree nodes with their records, indenting each level.
+    fn tree<W: Write>(&self, wr: &mut W, nodes: &[Value], depth: usize) -> Result<(), Box<Error>> {
+        for node in nodes {
+            if let Some(records) = node.find("records").and_then(|v| v.as_array()) {
+                for record in records {
+                    try!(self.write(wr, record, depth));
+                }
+            }
 
-        write!(wr, "{}", color::Fg(AnsiValue::rgb(2, 2, 2))).unwrap();
+            if let Some(children) = node.find("children").and_then(|v| v.as_array()) {
+                try!(self.tree(wr, children, depth + 1));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a single line with the given record, indenting its message by the given depth.
+    fn write<W: Write>(&self, wr: &mut W, record: &Record, depth: usize) -> Result<(), Box<Error>> {
+        try!(write!(wr, "{}", color::Fg(AnsiValue::rgb(2, 2, 2))));
         if let Some(val) = record.find("timestamp") {
             if let Some(val) = val.as_i64() {
                 let msecs = val % NANOSECONDS_IN_SECOND;
@@ -52,17 +75,16 @@ impl Output for Dev {
                 match NaiveDateTime::from_timestamp_opt(timestamp, msecs as u32) {
                     Some(datetime) => {
                         let ts: DateTime<UTC> = DateTime::from_utc(datetime, UTC);
-                        write!(wr, "{}", ts.format("%Y-%m-%d %H:%M:%S%.6f %Z")).unwrap();
+                        try!(write!(wr, "{}", ts.format("%Y-%m-%d %H:%M:%S%.6f %Z")));
                     }
                     None => {
-                        warn!("failed to convert {} value into datetime", val);
-                        return;
+                        return Err(format!("failed to convert {} value into datetime", val).into());
                     }
                 }
             } else if let Some(val) = val.as_string() {
-                write!(wr, "{}", val).unwrap();
+                try!(write!(wr, "{}", val));
             } else {
-                unimplemented!();
+                return Err("field 'timestamp' must be either an integer or a string".into());
             }
         }
 
@@ -70,7 +92,7 @@ impl Output for Dev {
             if let Some(ch) = sev.chars().next() {
                 let color = color_from_severity(ch);
 
-                write!(wr, "{} {}", color, ch).unwrap();
+                try!(write!(wr, "{} {}", color, ch));
             }
         }
 
@@ -79,10 +101,10 @@ impl Output for Dev {
 
         match (pid, tid) {
             (Some(pid), Some(tid)) => {
-                write!(wr, " {:6.6}/{:#014.14x}", pid, tid).unwrap();
+                try!(write!(wr, " {:6.6}/{:#014.14x}", pid, tid));
             }
             (Some(pid), None) => {
-                write!(wr, " {:6.6}", pid).unwrap();
+                try!(write!(wr, " {:6.6}", pid));
             }
             (None, Some(..)) | (None, None) => {}
         }
@@ -91,7 +113,7 @@ impl Output for Dev {
         let line = record.find("lineno").and_then(|v| v.as_u64());
 
         if let (Some(module), Some(line)) = (module, line) {
-            write!(wr, " {:>14.14}:{:3}", module, line).unwrap();
+            try!(write!(wr, " {:>14.14}:{:3}", module, line));
         }
 
         let trcid = record.find("trace_id").and_then(|v| v.as_u64());
@@ -99,36 +121,55 @@ impl Output for Dev {
         let parid = record.find("parent_id").and_then(|v| v.as_u64());
 
         if let (Some(trcid), Some(spnid), Some(parid)) = (trcid, spnid, parid) {
-            write!(wr, " [{:6.6}:{:6.6}:{:6.6}]",
+            try!(write!(wr, " [{:6.6}:{:6.6}:{:6.6}]",
                 format!("{:#06.6x}", trcid),
                 format!("{:#06.6x}", spnid),
                 format!("{:#06.6x}", parid)
-            ).unwrap();
+            ));
         } else if let Some(trcid) = trcid {
-            write!(wr, " [{:6.6}:{:6}:{:6}]", format!("{:#0x}", trcid), ' ', ' ').unwrap();
+            try!(write!(wr, " [{:6.6}:{:6}:{:6}]", format!("{:#0x}", trcid), ' ', ' '));
         } else {
-            write!(wr, " [{:6}:{:6}:{:6}]", ' ', ' ', ' ').unwrap();
+            try!(write!(wr, " [{:6}:{:6}:{:6}]", ' ', ' ', ' '));
         }
 
         if let Some(val) = record.find("message") {
             if let Some(val) = val.as_string() {
-                write!(wr, " - {}{}", color::Fg(color::White), val).unwrap();
+                let indent: String = iter::repeat("  ").take(depth).collect();
+                try!(write!(wr, " - {}{}{}", color::Fg(color::White), indent, val));
             }
         }
 
-        write!(wr, "\r\n").unwrap();
+        try!(write!(wr, "\r\n"));
+
+        Ok(())
+    }
+}
+
+impl Output for Dev {
+    fn handle(&mut self, record: &Arc<Record>) -> Result<(), Box<Error>> {
+        let wr = stdout();
+        let mut wr = wr.lock();
+
+        try!(self.write(&mut wr, record, 0));
+
+        if let Some(spans) = record.lookup("trace.spans").and_then(|v| v.as_array()) {
+            try!(self.tree(&mut wr, spans, 1));
+        }
+
+        Ok(())
     }
 }
 
 impl OutputFactory for Dev {
-    type Error = &'static str;
+    type Error = Box<Error>;
 
     fn ty() -> &'static str {
         "dev"
     }
 
-    #[allow(unused_variables)]
-    fn from(cfg: &Config) -> Result<Box<Output>, Self::Error> {
+    fn from(cfg: &Config, _metrics: &Scope) -> Result<Box<Output>, Self::Error> {
+        let _: DevConfig = try!(config::decode(cfg));
+
         Ok(Box::new(Dev::new()))
     }
 }
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+
+use serde_json::{self, Value};
+
+use {Config, Record};
+use config;
+use expr::Predicate;
+use filter::pointer::Pointer;
+use metrics::{Counter, Histogram, Scope};
+use output::{Output, OutputFactory};
+use output::config::{ExporterConfig, MetricConfig, MetricType};
+
+/// Prefix reserved for internal metrics.
+const RESERVED_PREFIX: &'static str = "zenlog_";
+
+/// Returns whether the given string is a valid Prometheus metric or label name.
+///
+/// Colons are allowed in metric names only.
+fn is_valid_name(name: &str, colons: bool) -> bool {
+    let valid = |ch: char, first: bool| {
+        ch == '_' || (colons && ch == ':') || (ch >= 'a' && ch <= 'z') ||
+            (ch >= 'A' && ch <= 'Z') || (!first && ch >= '0' && ch <= '9')
+    };
+
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(ch) if valid(ch, true) => chars.all(|ch| valid(ch, false)),
+        _ => false,
+    }
+}
+
+/// Returns the label value for the given field value, missing fields map to an empty string.
+fn label(value: Option<&Value>) -> String {
+    match value {
+        None | Some(&Value::Null) => String::new(),
+        Some(&Value::String(ref val)) => val.clone(),
+        Some(val) => serde_json::to_string(val).unwrap_or_else(|_| String::new()),
+    }
+}
+
+enum Handle {
+    Counter(Counter),
+    Histogram(Histogram),
+}
+
+/// User-defined metric with its series, keyed by label values.
+struct Metric {
+    name: String,
+    help: String,
+    ty: MetricType,
+    when: Option<Predicate>,
+    field: Option<Pointer>,
+    labels: Vec<(String, Pointer)>,
+    buckets: Vec<f64>,
+    series: HashMap<Vec<String>, Handle>,
+}
+
+impl Metric {
+    fn new(cfg: &MetricConfig, scope: &Scope) -> Result<Metric, Box<Error>> {
+        let name = cfg.name();
+
+        if !is_valid_name(name, true) {
+            return Err(format!("invalid metric name '{}'", name).into());
+        }
+
+        if name.starts_with(RESERVED_PREFIX) {
+            return Err(format!("metric name '{}' uses reserved '{}' prefix", name,
+                RESERVED_PREFIX).into());
+        }
+
+        for label in cfg.labels().keys() {
+            if !is_valid_name(label, false) || label.starts_with("__") {
+                return Err(format!("invalid label name '{}' of metric '{}'", label, name).into());
+            }
+
+            if scope.has_label(label) || (cfg.ty() == MetricType::Histogram && label == "le") {
+                return Err(format!("label name '{}' of metric '{}' is reserved", label, name)
+                    .into());
+            }
+        }
+
+        let buckets = cfg.buckets();
+
+        match cfg.ty() {
+            MetricType::Counter => try!(scope.declare_counter(name, cfg.help())),
+            MetricType::Histogram => {
+                if cfg.field().is_none() {
+                    return Err(format!("histogram '{}' requires 'field'", name).into());
+                }
+
+                if buckets.is_empty() || buckets.windows(2).any(|pair| pair[0] >= pair[1]) {
+                    return Err(format!("buckets of histogram '{}' must be increasing", name)
+                        .into());
+                }
+
+                try!(scope.declare_histogram(name, cfg.help()));
+            }
+        }
+
+        let metric = Metric {
+            name: name.to_owned(),
+            help: cfg.help().to_owned(),
+            ty: cfg.ty(),
+            when: cfg.when().cloned(),
+            field: cfg.field().cloned(),
+            labels: cfg.labels().iter()
+                .map(|(label, field)| (label.clone(), field.clone()))
+                .collect(),
+            buckets: buckets,
+            series: HashMap::new(),
+        };
+
+        Ok(metric)
+    }
+
+    /// Returns the scope of the series with the given label values.
+    fn scope(&self, scope: &Scope, values: &[String]) -> Scope {
+        self.labels.iter()
+            .zip(values.iter())
+            .fold(scope.clone(), |scope, (&(ref label, _), value)| scope.with(label, value))
+    }
+
+    /// Registers the series with labels of the given scope.
+    fn register(&self, scope: &Scope) -> Handle {
+        match self.ty {
+            MetricType::Counter => Handle::Counter(scope.counter(&self.name, &self.help)),
+            MetricType::Histogram => {
+                Handle::Histogram(scope.histogram(&self.name, &self.help, &self.buckets))
+            }
+        }
+    }
+}
+
+/// Turns matching records into user-defined counters and histograms, that are served on the
+/// metrics endpoint along with internal ones.
+///
+/// The number of label combinations is limited per metric to protect from unbounded memory
+/// growth on high-cardinality fields. Series are never removed from the metrics registry, so the
+/// limit accounts for series registered by all exporters, including ones before reloads.
+pub struct Exporter {
+    scope: Scope,
+    metrics: Vec<Metric>,
+    max_series: usize,
+    overflows: Counter,
+    invalid: Counter,
+}
+
+impl Exporter {
+    fn new(cfg: ExporterConfig, scope: &Scope) -> Result<Exporter, Box<Error>> {
+        let mut metrics = Vec::new();
+        for metric in cfg.metrics() {
+            metrics.push(try!(Metric::new(metric, scope)));
+        }
+
+        let exporter = Exporter {
+            scope: scope.clone(),
+            metrics: metrics,
+            max_series: cfg.max_series(),
+            overflows: scope.counter("zenlog_output_series_overflows_total",
+                "Number of observations dropped because of the series limit"),
+            invalid: scope.counter("zenlog_output_invalid_values_total",
+                "Number of counter increments dropped because of invalid field values"),
+        };
+
+        Ok(exporter)
+    }
+}
+
+impl Output for Exporter {
+    fn handle(&mut self, record: &Arc<Record>) -> Result<(), Box<Error>> {
+        for metric in &mut self.metrics {
+            if let Some(ref when) = metric.when {
+                if !when.matches(record) {
+                    continue;
+                }
+            }
+
+            let value = match metric.field {
+                Some(ref field) => {
+                    match field.find(record).and_then(|val| val.as_f64()) {
+                        Some(val) => Some(val),
+                        None => continue,
+                    }
+                }
+                None => None,
+            };
+
+            let values: Vec<String> = metric.labels.iter()
+                .map(|&(_, ref field)| label(field.find(record)))
+                .collect();
+
+            if !metric.series.contains_key(&values) {
+                let scope = metric.scope(&self.scope, &values);
+
+                if !scope.has_series(&metric.name) &&
+                    scope.series_count(&metric.name) >= self.max_series
+                {
+                    self.overflows.inc();
+                    continue;
+                }
+
+                let handle = metric.register(&scope);
+                metric.series.insert(values.clone(), handle);
+            }
+
+            match metric.series[&values] {
+                Handle::Counter(ref counter) => {
+                    match value {
+                        // Counters are integral, so fractional increments are rejected rather
+                        // than silently floored.
+                        Some(val) if val >= 0.0 && val.fract() == 0.0 => {
+                            counter.add(val as usize)
+                        }
+                        Some(..) => self.invalid.inc(),
+                        None => counter.inc(),
+                    }
+                }
+                Handle::Histogram(ref histogram) => {
+                    histogram.observe(value.unwrap_or(0.0));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl OutputFactory for Exporter {
+    type Error = Box<Error>;
+
+    fn ty() -> &'static str {
+        "metrics"
+    }
+
+    fn from(cfg: &Config, metrics: &Scope) -> Result<Box<Output>, Self::Error> {
+        let cfg: ExporterConfig = try!(config::decode(cfg));
+
+        Ok(Box::new(try!(Exporter::new(cfg, metrics))))
+    }
+}
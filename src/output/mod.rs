@@ -8,18 +8,25 @@
 //!
 //! The result: each output manages its blocking mode itself.
 
+mod config;
 mod dev;
+mod exporter;
 
 pub use self::dev::Dev;
+pub use self::exporter::Exporter;
 
 use std::error::Error;
 use std::sync::Arc;
 use std::sync::mpsc::Sender;
 
 use super::{Config, Record};
+use metrics::Scope;
 
 pub trait Output: Send {
-    fn handle(&mut self, record: &Arc<Record>);
+    /// Handles the given record.
+    ///
+    /// Errors are accounted in the pipeline metrics and logged, but never stop the pipeline.
+    fn handle(&mut self, record: &Arc<Record>) -> Result<(), Box<Error>>;
 
     /// Creates an optional sender, which should be triggered when it's time to reload the output.
     ///
@@ -39,6 +46,8 @@ pub trait OutputFactory {
     fn ty() -> &'static str where Self: Sized;
 
     /// Constructs the output by configuring it with the given config.
-    fn from(cfg: &Config) -> Result<Box<Output>, Self::Error>
+    ///
+    /// The given metrics scope is already labelled with both pipeline name and output type.
+    fn from(cfg: &Config, metrics: &Scope) -> Result<Box<Output>, Self::Error>
         where Self: Sized;
 }
@@ -0,0 +1,161 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+
+use Record;
+
+/// Severity level, ordered from the most severe to the most verbose.
+///
+/// Can be deserialized either from its name, case-insensitively, or from its number, where `0`
+/// means `ERROR` and `4` means `TRACE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Severity {
+    /// Returns severity with the given number, if any.
+    pub fn from_usize(val: usize) -> Option<Severity> {
+        match val {
+            0 => Some(Severity::Error),
+            1 => Some(Severity::Warn),
+            2 => Some(Severity::Info),
+            3 => Some(Severity::Debug),
+            4 => Some(Severity::Trace),
+            _ => None,
+        }
+    }
+
+    /// Returns severity with the given name, case-insensitively.
+    ///
+    /// Common aliases used by various logging libraries, like `WARNING` or `CRITICAL`, are also
+    /// recognized.
+    pub fn from_name(name: &str) -> Option<Severity> {
+        match &name.to_uppercase()[..] {
+            "CRITICAL" | "FATAL" | "ERROR" | "ERR" | "E" => Some(Severity::Error),
+            "WARNING" | "WARN" | "W" => Some(Severity::Warn),
+            "INFO" | "NOTICE" | "I" => Some(Severity::Info),
+            "DEBUG" | "D" => Some(Severity::Debug),
+            "TRACE" | "T" => Some(Severity::Trace),
+            _ => None,
+        }
+    }
+
+    /// Extracts severity from the given record.
+    ///
+    /// The following fields are checked in order:
+    ///  - `levelname` - severity name, i.e. "WARNING".
+    ///  - `severity` - either severity name, its number or `[number, name]` pair.
+    ///  - `levelno` - Python logging level number, i.e. 30 for warnings.
+    pub fn from_record(record: &Record) -> Option<Severity> {
+        if let Some(sev) = record.find("levelname").and_then(|v| v.as_string()) {
+            return Severity::from_name(sev);
+        }
+
+        if let Some(val) = record.find("severity") {
+            if let Some(sev) = val.as_string() {
+                return Severity::from_name(sev);
+            }
+
+            if let Some(sev) = val.as_u64() {
+                return Severity::from_usize(sev as usize);
+            }
+
+            if let Some(vec) = val.as_array() {
+                return vec.get(1)
+                    .and_then(|v| v.as_string())
+                    .and_then(Severity::from_name)
+                    .or_else(|| {
+                        vec.get(0)
+                            .and_then(|v| v.as_u64())
+                            .and_then(|v| Severity::from_usize(v as usize))
+                    });
+            }
+        }
+
+        if let Some(val) = record.find("levelno").and_then(|v| v.as_u64()) {
+            let sev = match val {
+                val if val >= 40 => Severity::Error,
+                val if val >= 30 => Severity::Warn,
+                val if val >= 20 => Severity::Info,
+                val if val >= 10 => Severity::Debug,
+                _ => Severity::Trace,
+            };
+
+            return Some(sev);
+        }
+
+        None
+    }
+
+    pub fn as_usize(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Severity::Error => "ERROR",
+            Severity::Warn => "WARN",
+            Severity::Info => "INFO",
+            Severity::Debug => "DEBUG",
+            Severity::Trace => "TRACE",
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    /// Parses severity the same way as record fields are, so aliases like `warning` are accepted.
+    fn from_str(val: &str) -> Result<Severity, String> {
+        Severity::from_name(val).ok_or_else(|| format!("invalid severity '{}'", val))
+    }
+}
+
+struct SeverityVisitor;
+
+impl Visitor for SeverityVisitor {
+    type Value = Severity;
+
+    fn visit_str<E>(&mut self, val: &str) -> Result<Severity, E>
+        where E: de::Error
+    {
+        val.parse().map_err(E::custom)
+    }
+
+    fn visit_u64<E>(&mut self, val: u64) -> Result<Severity, E>
+        where E: de::Error
+    {
+        Severity::from_usize(val as usize)
+            .ok_or(E::custom(format!("invalid severity {}, must be in [0; 4] range", val)))
+    }
+
+    fn visit_i64<E>(&mut self, val: i64) -> Result<Severity, E>
+        where E: de::Error
+    {
+        if val < 0 {
+            return Err(E::custom(format!("invalid severity {}, must be in [0; 4] range", val)));
+        }
+
+        self.visit_u64(val as u64)
+    }
+}
+
+impl Deserialize for Severity {
+    fn deserialize<D>(de: &mut D) -> Result<Severity, D::Error>
+        where D: Deserializer
+    {
+        de.deserialize(SeverityVisitor)
+    }
+}
@@ -0,0 +1,131 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+
+use config::LimitsConfig;
+
+/// Default maximum line length in line-oriented mode, 1 MiB.
+const MAX_LINE_LENGTH: usize = 1024 * 1024;
+
+/// Describes how the input byte stream is split into records.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Framing {
+    /// Records are decoded as a continuous JSON stream, possibly spanning several lines.
+    ///
+    /// There is no way to resynchronize the stream after a decoding error, so reading stops on
+    /// the first malformed record.
+    Stream,
+    /// Each line is a single record. Malformed or too long lines are skipped.
+    Line,
+}
+
+impl FromStr for Framing {
+    type Err = String;
+
+    fn from_str(val: &str) -> Result<Framing, String> {
+        match val {
+            "stream" => Ok(Framing::Stream),
+            "line" => Ok(Framing::Line),
+            _ => Err(format!("invalid framing '{}', must be either 'stream' or 'line'", val)),
+        }
+    }
+}
+
+impl Deserialize for Framing {
+    fn deserialize<D>(de: &mut D) -> Result<Framing, D::Error>
+        where D: Deserializer
+    {
+        let val = try!(String::deserialize(de));
+        val.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Describes what to do when the input is exhausted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnEof {
+    /// Close the source. The pipeline stops when all of its sources are closed.
+    Pipeline,
+    /// Gracefully shut down the whole runtime, like SIGTERM does.
+    Runtime,
+}
+
+impl FromStr for OnEof {
+    type Err = String;
+
+    fn from_str(val: &str) -> Result<OnEof, String> {
+        match val {
+            "pipeline" => Ok(OnEof::Pipeline),
+            "runtime" => Ok(OnEof::Runtime),
+            _ => {
+                Err(format!("invalid EOF policy '{}', must be either 'pipeline' or 'runtime'", val))
+            }
+        }
+    }
+}
+
+impl Deserialize for OnEof {
+    fn deserialize<D>(de: &mut D) -> Result<OnEof, D::Error>
+        where D: Deserializer
+    {
+        let val = try!(String::deserialize(de));
+        val.parse().map_err(de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StdinConfig {
+    framing: Option<Framing>,
+    max_line_length: Option<usize>,
+    on_eof: Option<OnEof>,
+    /// Limits applied to decoded records.
+    limits: Option<LimitsConfig>,
+}
+
+impl StdinConfig {
+    pub fn framing(&self) -> Framing {
+        self.framing.unwrap_or(Framing::Stream)
+    }
+
+    pub fn max_line_length(&self) -> usize {
+        self.max_line_length.unwrap_or(MAX_LINE_LENGTH)
+    }
+
+    pub fn on_eof(&self) -> OnEof {
+        self.on_eof.unwrap_or(OnEof::Pipeline)
+    }
+
+    pub fn limits(&self) -> Option<&LimitsConfig> {
+        self.limits.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UdpConfig {
+    /// Address to listen on, i.e. "127.0.0.1:50031".
+    endpoint: String,
+    /// Field to store the sender address in, i.e. "peer". Not stored by default.
+    ///
+    /// Useful for telling apart records of different senders, i.e. by the multiline filter.
+    peer: Option<String>,
+    /// Limits applied to decoded records.
+    limits: Option<LimitsConfig>,
+}
+
+impl UdpConfig {
+    pub fn endpoint(&self) -> Result<SocketAddr, Box<Error>> {
+        self.endpoint.parse()
+            .map_err(|err| format!("invalid endpoint '{}': {}", self.endpoint, err).into())
+    }
+
+    pub fn peer(&self) -> Option<&str> {
+        self.peer.as_ref().map(|peer| peer.as_str())
+    }
+
+    pub fn limits(&self) -> Option<&LimitsConfig> {
+        self.limits.as_ref()
+    }
+}
@@ -3,10 +3,13 @@ use std::sync::Arc;
 use std::sync::mpsc::Sender;
 
 use super::{Config, Record};
+use admin::Shutdown;
+use metrics::Scope;
 
 pub use self::stdin::StdinSource;
 pub use self::udp::UdpSource;
 
+mod config;
 mod stdin;
 mod udp;
 
@@ -21,6 +24,11 @@ pub trait SourceFactory {
         where Self: Sized;
 
     /// Constructs and immediately run a new source by configuring it with the given config.
-    fn run(cfg: &Config, tx: Sender<Arc<Record>>) -> Result<Box<Source>, Self::Error>
+    ///
+    /// The given metrics scope is already labelled with both pipeline name and source type. The
+    /// shutdown handle allows the source to stop the entire runtime, i.e. when its input is
+    /// exhausted.
+    fn run(cfg: &Config, tx: Sender<Arc<Record>>, metrics: &Scope, shutdown: &Shutdown) ->
+        Result<Box<Source>, Self::Error>
         where Self: Sized;
 }
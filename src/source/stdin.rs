@@ -1,38 +1,205 @@
 use std::error::Error;
-use std::io::{stdin, BufReader, Read};
+use std::io::{stdin, BufRead, BufReader, Read};
 use std::sync::Arc;
 use std::sync::mpsc::Sender;
 use std::thread;
 
-use serde_json::StreamDeserializer;
+use serde_json::{self, StreamDeserializer};
 
 use {Config, Record};
+use admin::Shutdown;
+use config;
+use health::Probe;
+use limits::Limits;
+use metrics::{Counter, Scope};
 use source::{Source, SourceFactory};
+use source::config::{Framing, OnEof, StdinConfig};
 
-pub struct StdinSource;
+struct StdinMetrics {
+    records: Counter,
+    errors: Counter,
+    truncated: Counter,
+}
+
+impl StdinMetrics {
+    fn new(scope: &Scope) -> StdinMetrics {
+        StdinMetrics {
+            records: scope.counter("zenlog_source_records_total",
+                "Number of records produced by the source"),
+            errors: scope.counter("zenlog_source_decode_errors_total",
+                "Number of payloads the source failed to decode"),
+            truncated: scope.counter("zenlog_source_truncated_total",
+                "Number of records truncated to fit the source limits"),
+        }
+    }
+}
+
+/// Reads records from the standard input.
+///
+/// # Note
+///
+/// Combined with `on_eof: runtime` this source allows to use Zenlog as a batch converter, i.e.
+/// `zenlog < app.log`.
+pub struct StdinSource {
+    /// Keeps the reading thread registered in the liveness registry.
+    _probe: Probe,
+}
 
 impl StdinSource {
-    fn new(tx: Sender<Arc<Record>>) -> Result<StdinSource, Box<Error>> {
+    fn new(cfg: StdinConfig, tx: Sender<Arc<Record>>, metrics: &Scope, shutdown: Shutdown) ->
+        Result<StdinSource, Box<Error>>
+    {
+        if cfg.max_line_length() == 0 {
+            return Err("max_line_length must be positive".into());
+        }
+
+        let probe = metrics.probe();
+        let guard = probe.guard();
+        let metrics = StdinMetrics::new(metrics);
+
         thread::spawn(move || {
             let rd = stdin();
             let rd = rd.lock();
             let rd = BufReader::new(rd);
-            for record in StreamDeserializer::new(rd.bytes()) {
-                match record {
-                    Ok(record) => {
-                        tx.send(Arc::new(record))
-                            .expect("pipeline must outlive all attached inputs");
-                    }
-                    Err(err) => {
-                        warn!("unable to decode payload - {}", err);
-                        break;
-                    }
-                }
+
+            let max = cfg.max_line_length();
+            let limits = cfg.limits().map(Limits::new);
+            let limits = limits.as_ref();
+
+            match cfg.framing() {
+                Framing::Stream => StdinSource::read_stream(rd, limits, &tx, &metrics),
+                Framing::Line => StdinSource::read_lines(rd, max, limits, &tx, &metrics),
+            }
+
+            debug!("stdin has been exhausted");
+            guard.finish();
+
+            // Let the pipeline see the disconnect, because the shutdown drops it.
+            drop(tx);
+
+            if cfg.on_eof() == OnEof::Runtime {
+                // The main loop gracefully stops the runtime, draining all pipelines.
+                info!("shutting down the runtime, because stdin has been exhausted");
+                shutdown.request();
             }
         });
 
-        Ok(StdinSource)
+        let src = StdinSource {
+            _probe: probe,
+        };
+
+        Ok(src)
+    }
+
+    fn read_stream<R: Read>(rd: R, limits: Option<&Limits>, tx: &Sender<Arc<Record>>,
+        metrics: &StdinMetrics)
+    {
+        for record in StreamDeserializer::new(rd.bytes()) {
+            match record {
+                Ok(record) => StdinSource::send(record, limits, tx, metrics),
+                Err(err) => {
+                    metrics.errors.inc();
+                    warn!("unable to decode payload - {}, consider using 'line' framing", err);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn read_lines<R: BufRead>(mut rd: R, max: usize, limits: Option<&Limits>,
+        tx: &Sender<Arc<Record>>, metrics: &StdinMetrics)
+    {
+        let mut buf = Vec::new();
+        let mut lineno = 0;
+
+        loop {
+            buf.clear();
+            lineno += 1;
+
+            // Read at most `max` bytes plus delimiter to be able to detect too long lines without
+            // buffering them entirely.
+            let nread = match rd.by_ref().take(max as u64 + 1).read_until(b'\n', &mut buf) {
+                Ok(0) => break,
+                Ok(nread) => nread,
+                Err(err) => {
+                    error!("failed to read from stdin: {}", err);
+                    break;
+                }
+            };
+
+            if buf[nread - 1] != b'\n' && nread > max {
+                metrics.errors.inc();
+                warn!("skipping line {}: exceeds {} bytes limit", lineno, max);
+
+                if let Err(err) = StdinSource::skip_line(&mut rd) {
+                    error!("failed to read from stdin: {}", err);
+                    break;
+                }
+
+                continue;
+            }
+
+            let line = trim(&buf);
+            if line.is_empty() {
+                continue;
+            }
+
+            match serde_json::from_slice::<Record>(line) {
+                Ok(record) => StdinSource::send(record, limits, tx, metrics),
+                Err(err) => {
+                    metrics.errors.inc();
+                    warn!("skipping line {}: unable to decode payload - {}", lineno, err);
+                }
+            }
+        }
+    }
+
+    fn send(mut record: Record, limits: Option<&Limits>, tx: &Sender<Arc<Record>>,
+        metrics: &StdinMetrics)
+    {
+        if let Some(limits) = limits {
+            if limits.apply(&mut record) {
+                metrics.truncated.inc();
+            }
+        }
+
+        metrics.records.inc();
+        tx.send(Arc::new(record))
+            .expect("pipeline must outlive all attached inputs");
     }
+
+    /// Consumes the rest of the current line.
+    fn skip_line<R: BufRead>(rd: &mut R) -> Result<(), ::std::io::Error> {
+        loop {
+            let (found, nread) = {
+                let buf = try!(rd.fill_buf());
+                if buf.is_empty() {
+                    return Ok(());
+                }
+
+                match buf.iter().position(|&ch| ch == b'\n') {
+                    Some(pos) => (true, pos + 1),
+                    None => (false, buf.len()),
+                }
+            };
+
+            rd.consume(nread);
+
+            if found {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Strips trailing CR/LF characters.
+fn trim(buf: &[u8]) -> &[u8] {
+    let mut end = buf.len();
+    while end > 0 && (buf[end - 1] == b'\n' || buf[end - 1] == b'\r') {
+        end -= 1;
+    }
+
+    &buf[..end]
 }
 
 impl Source for StdinSource {}
@@ -44,9 +211,12 @@ impl SourceFactory for StdinSource {
         "stdin"
     }
 
-    #[allow(unused_variables)]
-    fn run(cfg: &Config, tx: Sender<Arc<Record>>) -> Result<Box<Source>, Box<Error>> {
-        StdinSource::new(tx)
+    fn run(cfg: &Config, tx: Sender<Arc<Record>>, metrics: &Scope, shutdown: &Shutdown) ->
+        Result<Box<Source>, Box<Error>>
+    {
+        let cfg = try!(config::decode(cfg));
+
+        StdinSource::new(cfg, tx, metrics, shutdown.clone())
             .map(|v| Box::new(v) as Box<Source>)
     }
 }
@@ -1,7 +1,6 @@
 use std::iter::repeat;
 use std::error::Error;
 use std::net::SocketAddr;
-use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::mpsc::Sender;
 use std::thread::{self, JoinHandle};
@@ -10,23 +9,62 @@ use mio;
 use mio::{EventLoop, Handler, Token, EventSet, PollOpt};
 use mio::udp::UdpSocket;
 
-use serde_json;
+use serde_json::{self, Value};
 
+use admin::Shutdown;
+use health::Probe;
+use limits::Limits;
+use metrics::{Counter, Scope};
 use source::{Source, SourceFactory};
+use source::config::UdpConfig;
 use {Config, Record};
+use config;
+
+struct UdpMetrics {
+    datagrams: Counter,
+    records: Counter,
+    errors: Counter,
+    truncated: Counter,
+}
+
+impl UdpMetrics {
+    fn new(scope: &Scope) -> UdpMetrics {
+        UdpMetrics {
+            datagrams: scope.counter("zenlog_source_datagrams_total",
+                "Number of datagrams received by the source"),
+            records: scope.counter("zenlog_source_records_total",
+                "Number of records produced by the source"),
+            errors: scope.counter("zenlog_source_decode_errors_total",
+                "Number of payloads the source failed to decode"),
+            truncated: scope.counter("zenlog_source_truncated_total",
+                "Number of records truncated to fit the source limits"),
+        }
+    }
+}
 
 struct UdpHandler {
     socket: UdpSocket,
     tx: Sender<Arc<Record>>,
     buf: Vec<u8>,
+    peer: Option<String>,
+    limits: Option<Limits>,
+    metrics: UdpMetrics,
+    /// Whether the event loop has been stopped by the owner, rather than by an error.
+    stopped: bool,
 }
 
 impl UdpHandler {
-    pub fn new(tx: Sender<Arc<Record>>, socket: UdpSocket) -> UdpHandler {
+    fn new(tx: Sender<Arc<Record>>, socket: UdpSocket, peer: Option<String>,
+        limits: Option<Limits>, metrics: UdpMetrics) -> UdpHandler
+    {
         UdpHandler {
             socket: socket,
             tx: tx,
             buf: repeat(0).take(16 * 1024).collect(),
+            peer: peer,
+            limits: limits,
+            metrics: metrics,
+            stopped: false,
         }
     }
 }
@@ -43,13 +81,28 @@ impl Handler for UdpHandler {
             match self.socket.recv_from(&mut self.buf[..]) {
                 Ok(Some((nread, endpoint))) => {
                     debug!("read {} bytes datagram from {}", nread, endpoint);
+                    self.metrics.datagrams.inc();
 
                     match serde_json::from_slice::<Record>(&self.buf[..nread]) {
-                        Ok(record) => {
+                        Ok(mut record) => {
+                            if let Some(ref field) = self.peer {
+                                if let Some(map) = record.as_object_mut() {
+                                    map.insert(field.clone(), Value::String(endpoint.to_string()));
+                                }
+                            }
+
+                            if let Some(ref limits) = self.limits {
+                                if limits.apply(&mut record) {
+                                    self.metrics.truncated.inc();
+                                }
+                            }
+
+                            self.metrics.records.inc();
                             self.tx.send(Arc::new(record))
                                 .expect("pipeline must outlive all attached inputs");
                         }
                         Err(err) => {
+                            self.metrics.errors.inc();
                             warn!("unable to decode datagram - {}", err);
                         }
                     }
@@ -68,6 +121,7 @@ impl Handler for UdpHandler {
     }
 
     fn notify(&mut self, ev: &mut EventLoop<UdpHandler>, _: ()) {
+        self.stopped = true;
         ev.shutdown();
     }
 }
@@ -75,24 +129,37 @@ impl Handler for UdpHandler {
 pub struct UdpSource {
     stop: mio::Sender<()>,
     thread: Option<JoinHandle<()>>,
+    /// Keeps the event loop thread registered in the liveness registry.
+    _probe: Probe,
 }
 
 impl UdpSource {
-    fn new(endpoint: &SocketAddr, tx: Sender<Arc<Record>>) -> Result<UdpSource, Box<Error>> {
+    fn new(endpoint: &SocketAddr, peer: Option<String>, limits: Option<Limits>,
+        tx: Sender<Arc<Record>>, metrics: &Scope) -> Result<UdpSource, Box<Error>>
+    {
         let listener = try!(UdpSocket::bound(endpoint));
         info!(target: "UDP input", "exposed UDP input on {}", endpoint);
 
         let mut ev = try!(EventLoop::new());
+        let probe = metrics.probe();
+        let guard = probe.guard();
+        let metrics = UdpMetrics::new(metrics);
 
         let stop = ev.channel();
         let thread = thread::spawn(move || {
             ev.register(&listener, Token(0), EventSet::readable(), PollOpt::edge()).unwrap();
-            ev.run(&mut UdpHandler::new(tx, listener)).unwrap();
+            let mut handler = UdpHandler::new(tx, listener, peer, limits, metrics);
+            ev.run(&mut handler).unwrap();
+
+            if handler.stopped {
+                guard.finish();
+            }
         });
 
         let src = UdpSource {
             stop: stop,
             thread: Some(thread),
+            _probe: probe,
         };
 
         Ok(src)
@@ -108,13 +175,14 @@ impl SourceFactory for UdpSource {
         "udp"
     }
 
-    fn run(cfg: &Config, tx: Sender<Arc<Record>>) -> Result<Box<Source>, Box<Error>> {
-        let endpoint = cfg.find("endpoint")
-            .expect("field 'endpoint' is required")
-            .as_string()
-            .expect("field 'endpoint' must be a string");
+    fn run(cfg: &Config, tx: Sender<Arc<Record>>, metrics: &Scope, _shutdown: &Shutdown) ->
+        Result<Box<Source>, Box<Error>>
+    {
+        let cfg: UdpConfig = try!(config::decode(cfg));
+
+        let peer = cfg.peer().map(|peer| peer.to_owned());
 
-        UdpSource::new(&FromStr::from_str(endpoint).unwrap(), tx)
+        UdpSource::new(&try!(cfg.endpoint()), peer, cfg.limits().map(Limits::new), tx, metrics)
             .map(|v| Box::new(v) as Box<Source>)
     }
 }